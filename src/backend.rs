@@ -0,0 +1,144 @@
+mod discard;
+#[cfg(target_os = "android")]
+mod liblog;
+#[cfg(unix)]
+mod logd;
+mod memory;
+
+use std::{ffi::CStr, io};
+
+use tracing_core::{Level, LevelFilter};
+
+use crate::logging::{Buffer, Priority};
+
+pub use self::discard::Discard;
+#[cfg(all(target_os = "android", feature = "api-30"))]
+pub(crate) use self::liblog::is_writing;
+#[cfg(target_os = "android")]
+pub use self::liblog::LibLog;
+#[cfg(unix)]
+pub use self::logd::Logd;
+#[cfg(unix)]
+pub(crate) use self::logd::{current_tid, header};
+pub use self::memory::{Memory, MemoryEvent, MemoryRecord};
+
+/// The [`Backend`] used when none is specified.
+///
+/// This is [`LibLog`] on Android and [`Discard`] on every other target,
+/// so that code using this crate can be built and run off-device.
+#[cfg(target_os = "android")]
+pub type DefaultBackend = LibLog;
+/// The [`Backend`] used when none is specified.
+///
+/// This is `LibLog` on Android and [`Discard`] on every other target,
+/// so that code using this crate can be built and run off-device.
+#[cfg(not(target_os = "android"))]
+pub type DefaultBackend = Discard;
+
+/// A destination for the records produced by [`AndroidLogWriter`](crate::AndroidLogWriter).
+pub trait Backend {
+    /// Writes a single record.
+    fn write(&self, record: &Record<'_>) -> io::Result<()>;
+
+    /// Writes a binary [event log](crate::Buffer::Events) record with the given tag and encoded payload.
+    ///
+    /// The default implementation returns an [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn write_event(&self, tag: u32, payload: &[u8]) -> io::Result<()> {
+        let _ = (tag, payload);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Returns whether a record with the given priority and tag would be written.
+    ///
+    /// This is checked once per event, before any record is written.
+    fn is_loggable(&self, priority: Priority, tag: &CStr) -> bool {
+        let _ = (priority, tag);
+        true
+    }
+
+    /// Returns the minimum priority of the records written by this backend, if one is set.
+    ///
+    /// The default implementation returns `None`.
+    fn minimum_priority(&self) -> Option<Priority> {
+        None
+    }
+
+    /// Sets the minimum priority of the records written by this backend,
+    /// which also applies to other code using the same backend, like native libraries logging through `liblog`.
+    ///
+    /// The default implementation returns an [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn set_minimum_priority(&self, priority: Priority) -> io::Result<()> {
+        let _ = priority;
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Sets the abort message of the process, which is included in the tombstone written when it aborts.
+    ///
+    /// The default implementation returns an [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn set_abort_message(&self, message: &CStr) -> io::Result<()> {
+        let _ = message;
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Sets the minimum priority of the given backend to match the current maximum level enabled by `tracing`,
+/// as returned by [`LevelFilter::current`].
+///
+/// This should be called whenever the filters of the subscriber are reloaded,
+/// for example from the callback of a [`PropertyWatcher`](crate::PropertyWatcher).
+/// Layers built with [`AndroidLayerBuilder::with_minimum_priority_sync`](crate::AndroidLayerBuilder::with_minimum_priority_sync)
+/// do this on their own, following their own filter rather than the maximum level of the whole subscriber.
+///
+/// ```rust
+/// use paranoid_android::{Backend, Memory, Priority};
+/// use tracing_subscriber::{filter::LevelFilter, prelude::*};
+///
+/// let memory = Memory::new();
+/// let subscriber = tracing_subscriber::registry().with(LevelFilter::WARN);
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     paranoid_android::sync_minimum_priority(&memory).unwrap();
+/// });
+/// assert_eq!(memory.minimum_priority(), Some(Priority::Warn));
+/// ```
+pub fn sync_minimum_priority<B: Backend + ?Sized>(backend: &B) -> io::Result<()> {
+    backend.set_minimum_priority(Priority::from_level_filter(LevelFilter::current()))
+}
+
+/// Returns the [`LevelFilter`] matching the minimum priority of the given backend,
+/// or [`LevelFilter::TRACE`] if none is set.
+///
+/// ```rust
+/// use paranoid_android::{Backend, Memory, Priority};
+/// use tracing_subscriber::filter::LevelFilter;
+///
+/// let memory = Memory::new();
+/// assert_eq!(paranoid_android::minimum_level(&memory), LevelFilter::TRACE);
+///
+/// memory.set_minimum_priority(Priority::Error).unwrap();
+/// assert_eq!(paranoid_android::minimum_level(&memory), LevelFilter::ERROR);
+/// ```
+pub fn minimum_level<B: Backend + ?Sized>(backend: &B) -> LevelFilter {
+    backend
+        .minimum_priority()
+        .map_or(LevelFilter::TRACE, |priority| Level::from(priority).into())
+}
+
+/// A single log record, as passed to a [`Backend`].
+///
+/// Messages longer than what logd accepts are split into several records before reaching the backend.
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    /// The log buffer to write to.
+    pub buffer: Buffer,
+    /// The priority of the record.
+    pub priority: Priority,
+    /// The tag of the record.
+    pub tag: &'a CStr,
+    /// The source file the record originates from, if known.
+    pub file: Option<&'a CStr>,
+    /// The source line the record originates from, if known.
+    pub line: Option<u32>,
+    /// The message of the record.
+    pub message: &'a CStr,
+}
@@ -0,0 +1,37 @@
+use std::{ffi::CStr, io};
+
+use crate::{
+    backend::{Backend, Record},
+    logging::Priority,
+};
+
+/// A [`Backend`] discarding every record, available on every target.
+///
+/// This is the [default backend](crate::DefaultBackend) off-device, so that code using this crate
+/// can run on a host without its logs piling up anywhere. Use [`Memory`](crate::Memory) to inspect them instead.
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Discard};
+/// use tracing_subscriber::fmt::MakeWriter;
+///
+/// let make_writer = AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Main, Discard);
+/// write!(make_writer.make_writer(), "hello").unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Discard;
+
+impl Backend for Discard {
+    fn write(&self, _: &Record<'_>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_event(&self, _: u32, _: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn is_loggable(&self, _: Priority, _: &CStr) -> bool {
+        false
+    }
+}
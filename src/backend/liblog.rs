@@ -0,0 +1,136 @@
+use std::{
+    ffi::CStr,
+    io,
+    os::raw::{c_int, c_void},
+};
+
+use crate::backend::{Backend, Record};
+#[cfg(feature = "api-30")]
+use crate::logging::Priority;
+
+extern "C" {
+    fn __android_log_bwrite(tag: i32, payload: *const c_void, len: usize) -> c_int;
+}
+
+/// A [`Backend`] writing records through Android's `liblog`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LibLog;
+
+impl Backend for LibLog {
+    fn write(&self, record: &Record<'_>) -> io::Result<()> {
+        let buffer = record.buffer.as_raw().0 as i32;
+        let priority = record.priority.as_raw().0 as i32;
+        let tag = record.tag.as_ptr();
+
+        #[cfg(feature = "api-30")]
+        {
+            use std::{mem::size_of, ptr::null};
+
+            use ndk_sys::{__android_log_message, __android_log_write_log_message};
+
+            let mut message = __android_log_message {
+                struct_size: size_of::<__android_log_message>(),
+                buffer_id: buffer,
+                priority,
+                tag,
+                file: record.file.map_or(null(), CStr::as_ptr),
+                line: record.line.unwrap_or(0),
+                message: record.message.as_ptr(),
+            };
+
+            // records written here must not be captured again by `capture_liblog`
+            let _writing = Writing::enter();
+            // this function doesn't report failures
+            unsafe { __android_log_write_log_message(&mut message) };
+            Ok(())
+        }
+
+        #[cfg(not(feature = "api-30"))]
+        {
+            use ndk_sys::__android_log_buf_write;
+
+            let result =
+                unsafe { __android_log_buf_write(buffer, priority, tag, record.message.as_ptr()) };
+            check(result)
+        }
+    }
+
+    fn write_event(&self, tag: u32, payload: &[u8]) -> io::Result<()> {
+        let result = unsafe {
+            __android_log_bwrite(tag as i32, payload.as_ptr() as *const c_void, payload.len())
+        };
+        check(result)
+    }
+
+    fn set_abort_message(&self, message: &CStr) -> io::Result<()> {
+        use ndk_sys::android_set_abort_message;
+
+        // only the first abort message is kept by bionic
+        unsafe { android_set_abort_message(message.as_ptr()) };
+        Ok(())
+    }
+
+    #[cfg(feature = "api-30")]
+    fn is_loggable(&self, priority: Priority, tag: &CStr) -> bool {
+        use ndk_sys::__android_log_is_loggable;
+
+        let priority = priority.as_raw().0 as i32;
+        unsafe { __android_log_is_loggable(priority, tag.as_ptr(), priority) != 0 }
+    }
+
+    #[cfg(feature = "api-30")]
+    fn minimum_priority(&self) -> Option<Priority> {
+        use ndk_sys::__android_log_get_minimum_priority;
+
+        // `ANDROID_LOG_DEFAULT` means no minimum priority is set
+        Priority::from_raw(unsafe { __android_log_get_minimum_priority() })
+    }
+
+    #[cfg(feature = "api-30")]
+    fn set_minimum_priority(&self, priority: Priority) -> io::Result<()> {
+        use ndk_sys::__android_log_set_minimum_priority;
+
+        unsafe { __android_log_set_minimum_priority(priority.as_raw().0 as i32) };
+        Ok(())
+    }
+}
+
+#[cfg(feature = "api-30")]
+thread_local! {
+    /// Whether a record is being written through `liblog` on this thread.
+    static WRITING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Marks the current thread as writing a record through `liblog` until dropped.
+#[cfg(feature = "api-30")]
+struct Writing;
+
+#[cfg(feature = "api-30")]
+impl Writing {
+    fn enter() -> Self {
+        let _ = WRITING.try_with(|w| w.set(true));
+        Writing
+    }
+}
+
+#[cfg(feature = "api-30")]
+impl Drop for Writing {
+    fn drop(&mut self) {
+        let _ = WRITING.try_with(|w| w.set(false));
+    }
+}
+
+/// Returns whether a record is being written through `liblog` by this crate on the current thread.
+#[cfg(feature = "api-30")]
+pub(crate) fn is_writing() -> bool {
+    WRITING.try_with(|w| w.get()).unwrap_or(false)
+}
+
+/// Converts the negative errno returned by `liblog` on failure to an error.
+fn check(result: c_int) -> io::Result<()> {
+    if result < 0 {
+        Err(io::Error::from_raw_os_error(-result))
+    } else {
+        Ok(())
+    }
+}
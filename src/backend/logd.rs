@@ -0,0 +1,166 @@
+use std::{
+    io::{self, IoSlice},
+    os::unix::{io::AsRawFd, net::UnixDatagram},
+    path::Path,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    backend::{Backend, Record},
+    logging::Buffer,
+};
+
+/// A [`Backend`] writing records directly to logd's `logdw` socket, bypassing `liblog`.
+///
+/// Each record is sent as a single datagram following logd's wire protocol,
+/// which is a packed header made of the log buffer ID (`u8`), the thread ID (`u16`)
+/// and the realtime timestamp (`u32` seconds, `u32` nanoseconds), all little endian,
+/// followed by the priority (`u8`), the NUL-terminated tag and the NUL-terminated message.
+///
+/// ```rust
+/// use std::os::unix::net::UnixDatagram;
+///
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Logd};
+/// use tracing_subscriber::fmt::MakeWriter;
+///
+/// let path = std::env::temp_dir().join(format!("logdw-{}", std::process::id()));
+/// let logd = UnixDatagram::bind(&path).unwrap();
+///
+/// let make_writer = AndroidLogMakeWriter::with_backend(
+///     "tag".to_owned(),
+///     Buffer::Main,
+///     Logd::with_path(&path).unwrap(),
+/// );
+/// std::io::Write::write_all(&mut make_writer.make_writer(), b"hello").unwrap();
+///
+/// let mut datagram = [0; 64];
+/// let len = logd.recv(&mut datagram).unwrap();
+/// assert_eq!(datagram[0], 0); // main buffer
+/// assert_eq!(datagram[11], 4); // info priority
+/// assert_eq!(&datagram[12..len], b"tag\0hello\0");
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Logd {
+    socket: UnixDatagram,
+    clock: fn() -> SystemTime,
+    retries: u32,
+}
+
+/// Size of the header prepended to every datagram.
+pub(crate) const HEADER_LEN: usize = 11;
+
+impl Logd {
+    /// The path of logd's socket on Android devices.
+    pub const DEFAULT_PATH: &'static str = "/dev/socket/logdw";
+
+    /// Returns a new [`Logd`] backend connected to the [default socket](Self::DEFAULT_PATH).
+    pub fn new() -> io::Result<Self> {
+        Self::with_path(Self::DEFAULT_PATH)
+    }
+
+    /// Returns a new [`Logd`] backend connected to the datagram socket at the given path.
+    pub fn with_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            clock: SystemTime::now,
+            retries: 0,
+        })
+    }
+
+    /// Sets the clock used to timestamp records. Defaults to [`SystemTime::now`].
+    pub fn with_clock(self, clock: fn() -> SystemTime) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Sets how many times a write is retried when the socket buffer is full. Defaults to `0`.
+    pub fn with_retries(self, retries: u32) -> Self {
+        Self { retries, ..self }
+    }
+
+    fn send(&self, buffer: Buffer, payload: &[&[u8]]) -> io::Result<()> {
+        let timestamp = (self.clock)()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let header = header(
+            buffer,
+            current_tid(),
+            timestamp.as_secs() as u32,
+            timestamp.subsec_nanos(),
+        );
+
+        let mut iov = [IoSlice::new(&[]); 4];
+        iov[0] = IoSlice::new(&header);
+        for (slot, part) in iov[1..].iter_mut().zip(payload) {
+            *slot = IoSlice::new(part);
+        }
+        let iov = &iov[..=payload.len()];
+
+        let mut attempts = 0;
+        loop {
+            let result = unsafe {
+                libc::writev(
+                    self.socket.as_raw_fd(),
+                    iov.as_ptr() as *const libc::iovec,
+                    iov.len() as libc::c_int,
+                )
+            };
+            if result >= 0 {
+                return Ok(());
+            }
+
+            let error = io::Error::last_os_error();
+            match error.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock if attempts < self.retries => {
+                    attempts += 1;
+                    thread::yield_now();
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
+
+impl Backend for Logd {
+    fn write(&self, record: &Record<'_>) -> io::Result<()> {
+        let priority = [record.priority as u8];
+        self.send(
+            record.buffer,
+            &[
+                &priority,
+                record.tag.to_bytes_with_nul(),
+                record.message.to_bytes_with_nul(),
+            ],
+        )
+    }
+
+    fn write_event(&self, tag: u32, payload: &[u8]) -> io::Result<()> {
+        self.send(Buffer::Events, &[&tag.to_le_bytes(), payload])
+    }
+}
+
+/// Encodes a datagram header, without allocating.
+pub(crate) fn header(buffer: Buffer, tid: u16, sec: u32, nsec: u32) -> [u8; HEADER_LEN] {
+    let id = match buffer {
+        Buffer::Default => Buffer::Main,
+        buffer => buffer,
+    } as u32 as u8;
+
+    let mut header = [0; HEADER_LEN];
+    header[0] = id;
+    header[1..3].copy_from_slice(&tid.to_le_bytes());
+    header[3..7].copy_from_slice(&sec.to_le_bytes());
+    header[7..11].copy_from_slice(&nsec.to_le_bytes());
+    header
+}
+
+/// Returns the ID of the current thread, truncated like logd does.
+pub(crate) fn current_tid() -> u16 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u16 }
+}
@@ -0,0 +1,140 @@
+use std::{
+    ffi::CStr,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    backend::{Backend, Record},
+    logging::{Buffer, Priority},
+    sync::lock,
+};
+
+/// A [`Backend`] recording records in memory, available on every target.
+///
+/// Clones share the same records, so a clone can be kept around to inspect
+/// what was written after the original has been moved into a writer.
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory};
+/// use tracing_subscriber::fmt::MakeWriter;
+///
+/// let memory = Memory::new();
+/// let make_writer = AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Main, memory.clone());
+///
+/// write!(make_writer.make_writer(), "hello").unwrap();
+///
+/// let records = memory.take();
+/// assert_eq!(records.len(), 1);
+/// assert_eq!(records[0].tag, "tag");
+/// assert_eq!(records[0].buffer, Buffer::Main);
+/// assert_eq!(records[0].message, "hello");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    records: Arc<Mutex<Vec<MemoryRecord>>>,
+    events: Arc<Mutex<Vec<MemoryEvent>>>,
+    minimum_priority: Arc<Mutex<Option<Priority>>>,
+    abort_message: Arc<Mutex<Option<String>>>,
+}
+
+/// A record written to a [`Memory`] backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRecord {
+    /// The log buffer the record was written to.
+    pub buffer: Buffer,
+    /// The priority of the record.
+    pub priority: Priority,
+    /// The tag of the record.
+    pub tag: String,
+    /// The source file the record originates from, if known.
+    pub file: Option<String>,
+    /// The source line the record originates from, if known.
+    pub line: Option<u32>,
+    /// The message of the record.
+    pub message: String,
+}
+
+/// A binary [event log](crate::Buffer::Events) record written to a [`Memory`] backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryEvent {
+    /// The tag of the record.
+    pub tag: u32,
+    /// The encoded payload of the record.
+    pub payload: Vec<u8>,
+}
+
+impl Memory {
+    /// Returns a new, empty [`Memory`] backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of all the records written so far.
+    pub fn records(&self) -> Vec<MemoryRecord> {
+        lock(&self.records).clone()
+    }
+
+    /// Returns all the records written so far and clears them.
+    pub fn take(&self) -> Vec<MemoryRecord> {
+        std::mem::take(&mut *lock(&self.records))
+    }
+
+    /// Returns a copy of all the binary event log records written so far.
+    pub fn events(&self) -> Vec<MemoryEvent> {
+        lock(&self.events).clone()
+    }
+
+    /// Returns all the binary event log records written so far and clears them.
+    pub fn take_events(&self) -> Vec<MemoryEvent> {
+        std::mem::take(&mut *lock(&self.events))
+    }
+
+    /// Returns the abort message set so far, if any.
+    pub fn abort_message(&self) -> Option<String> {
+        lock(&self.abort_message).clone()
+    }
+}
+
+impl Backend for Memory {
+    fn write(&self, record: &Record<'_>) -> io::Result<()> {
+        lock(&self.records).push(MemoryRecord {
+            buffer: record.buffer,
+            priority: record.priority,
+            tag: record.tag.to_string_lossy().into_owned(),
+            file: record.file.map(|f| f.to_string_lossy().into_owned()),
+            line: record.line,
+            message: record.message.to_string_lossy().into_owned(),
+        });
+        Ok(())
+    }
+
+    fn write_event(&self, tag: u32, payload: &[u8]) -> io::Result<()> {
+        lock(&self.events).push(MemoryEvent {
+            tag,
+            payload: payload.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn is_loggable(&self, priority: Priority, _: &CStr) -> bool {
+        self.minimum_priority()
+            .map_or(true, |minimum| priority >= minimum)
+    }
+
+    fn minimum_priority(&self) -> Option<Priority> {
+        *lock(&self.minimum_priority)
+    }
+
+    fn set_minimum_priority(&self, priority: Priority) -> io::Result<()> {
+        *lock(&self.minimum_priority) = Some(priority);
+        Ok(())
+    }
+
+    fn set_abort_message(&self, message: &CStr) -> io::Result<()> {
+        *lock(&self.abort_message) = Some(message.to_string_lossy().into_owned());
+        Ok(())
+    }
+}
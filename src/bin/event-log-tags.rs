@@ -0,0 +1,90 @@
+//! Generates an `event-log-tags` file from a registry of event names and field types.
+//!
+//! The registry is read from the files given as arguments, or from the standard input,
+//! and contains one event per line, made of its name followed by its fields as `name:type` pairs,
+//! where `type` is one of `int`, `long`, `string`, `list` or `float`.
+//! Tag numbers are derived from event names using [`paranoid_android::stable_tag`],
+//! unless given explicitly as `name=tag`. Empty lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # events.txt
+//! battery_level level:long charging:int
+//! request_failed=1000042 path:string status:int
+//! ```
+//!
+//! ```sh
+//! event-log-tags events.txt > event-log-tags
+//! ```
+
+use std::{
+    env, fs,
+    io::{self, Read},
+    process,
+};
+
+use paranoid_android::{stable_tag, EventTags, EventType};
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("event-log-tags: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut registry = String::new();
+    let paths: Vec<_> = env::args_os().skip(1).collect();
+    if paths.is_empty() {
+        io::stdin()
+            .read_to_string(&mut registry)
+            .map_err(|e| e.to_string())?;
+    }
+    for path in paths {
+        let contents =
+            fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+        registry.push_str(&contents);
+        registry.push('\n');
+    }
+
+    let mut tags = EventTags::new();
+    for (i, line) in registry.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let event = parts.next().unwrap_or_default();
+        let (name, tag) = match event.split_once('=') {
+            Some((name, tag)) => {
+                let tag = tag
+                    .parse()
+                    .map_err(|_| format!("line {}: invalid tag number `{}`", i + 1, tag))?;
+                (name, tag)
+            }
+            None => (event, stable_tag(event)),
+        };
+
+        let mut fields = Vec::new();
+        for field in parts {
+            let (field, ty) = field
+                .split_once(':')
+                .ok_or_else(|| format!("line {}: missing type for field `{}`", i + 1, field))?;
+            let ty = match ty {
+                "int" => EventType::Int,
+                "long" => EventType::Long,
+                "string" => EventType::String,
+                "list" => EventType::List,
+                "float" => EventType::Float,
+                _ => return Err(format!("line {}: unknown field type `{}`", i + 1, ty)),
+            };
+            fields.push((field, ty));
+        }
+
+        tags.try_insert_fields(name, tag, fields)
+            .map_err(|e| format!("line {}: {} `{}`", i + 1, e, name))?;
+    }
+
+    tags.write_event_log_tags(io::stdout().lock())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,483 @@
+use std::{io, sync::Arc};
+
+use tracing_core::{span, subscriber::Interest, Event, Metadata, Subscriber};
+use tracing_subscriber::{
+    filter::{Filtered, LevelFilter},
+    fmt::{
+        self,
+        format::{Compact, Format, Full},
+        FormatEvent,
+    },
+    layer::{Context, Filter, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    Layer as _, Registry,
+};
+
+#[cfg(feature = "env-filter")]
+use tracing_subscriber::EnvFilter;
+
+#[cfg(feature = "env-filter")]
+use crate::property::system_properties;
+use crate::{
+    backend::{minimum_level, Backend, DefaultBackend},
+    fields::ReservedFields,
+    writer::PriorityMapping,
+    AndroidLogMakeWriter, Buffer, Error, FatalPolicy, Layer, NulPolicy, Overflow, Priority,
+    TagStrategy,
+};
+
+/// A builder collecting every option of the Android [layer](Layer) in one place.
+///
+/// ```rust
+/// use paranoid_android::{AndroidLayerBuilder, Buffer, Memory, Overflow, Priority, TagStrategy};
+/// use tracing::Level;
+/// use tracing_subscriber::{filter::LevelFilter, prelude::*};
+///
+/// let memory = Memory::new();
+/// let layer = AndroidLayerBuilder::new("app")
+///     .with_tag_strategy(TagStrategy::CrateName)
+///     .with_buffer(Buffer::Main)
+///     .with_backend(memory.clone())
+///     .with_priority_mapping(|meta| match *meta.level() {
+///         Level::ERROR => Some(Priority::Fatal),
+///         level => Some(level.into()),
+///     })
+///     .with_max_len(64)
+///     .with_overflow(Overflow::Truncate)
+///     .with_location(false)
+///     .compact()
+///     .with_filter(LevelFilter::INFO)
+///     .build();
+///
+/// tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+///     tracing::debug!(target: "my_app::net", "filtered");
+///     tracing::error!(target: "my_app::net", "oops");
+/// });
+///
+/// let records = memory.take();
+/// assert_eq!(records.len(), 1);
+/// assert_eq!((records[0].tag.as_str(), records[0].priority), ("my_app", Priority::Fatal));
+/// assert_eq!(records[0].file, None);
+/// ```
+#[derive(Debug)]
+pub struct AndroidLayerBuilder<E = Full, B = DefaultBackend, F = LevelFilter> {
+    tag: String,
+    tag_strategy: TagStrategy,
+    buffer: Buffer,
+    backend: B,
+    priority_mapping: PriorityMapping,
+    nul_policy: NulPolicy,
+    chunk_markers: bool,
+    max_len: Option<usize>,
+    overflow: Overflow,
+    location: bool,
+    fatal_policy: FatalPolicy,
+    format: Format<E, ()>,
+    filter: F,
+    /// Whether the filter was set by [`with_filter`](AndroidLayerBuilder::with_filter) rather than left to its default.
+    filter_set: bool,
+    minimum_priority_sync: bool,
+}
+
+/// A [`Layer`] wrapped in the [filter](AndroidLayerBuilder::with_filter) of an [`AndroidLayerBuilder`].
+pub type FilteredLayer<S, E = Full, B = DefaultBackend, F = LevelFilter> =
+    Filtered<Layer<S, ReservedFields, E, B>, SyncedFilter<F, B>, S>;
+
+/// The per-layer [`Filter`] of a [`FilteredLayer`], wrapping the [filter](AndroidLayerBuilder::with_filter) of the builder.
+///
+/// If [enabled](AndroidLayerBuilder::with_minimum_priority_sync), it sets the minimum priority of the backend
+/// to the maximum level of the wrapped filter whenever `tracing` rebuilds its filters,
+/// like after a [reload](tracing_subscriber::reload) or a [poll](crate::PropertyWatcher::poll) finding changes.
+#[derive(Debug)]
+pub struct SyncedFilter<F, B> {
+    filter: F,
+    /// The maximum level enabled, taken from the backend if the builder had no filter.
+    level: LevelFilter,
+    backend: Option<Arc<B>>,
+}
+
+impl AndroidLayerBuilder {
+    /// Returns a new [`AndroidLayerBuilder`] with the given tag.
+    pub fn new(tag: impl ToString) -> Self {
+        Self {
+            tag: tag.to_string(),
+            tag_strategy: Default::default(),
+            buffer: Default::default(),
+            backend: Default::default(),
+            priority_mapping: Default::default(),
+            nul_policy: Default::default(),
+            chunk_markers: false,
+            max_len: None,
+            overflow: Default::default(),
+            location: true,
+            fatal_policy: Default::default(),
+            format: Format::default().with_level(false).without_time(),
+            filter: LevelFilter::TRACE,
+            filter_set: false,
+            minimum_priority_sync: false,
+        }
+    }
+}
+
+impl<E, B, F> AndroidLayerBuilder<E, B, F> {
+    /// Sets how the tag of each record is derived from its target. Defaults to [`TagStrategy::Fixed`].
+    pub fn with_tag_strategy(self, tag_strategy: TagStrategy) -> Self {
+        Self {
+            tag_strategy,
+            ..self
+        }
+    }
+
+    /// Sets the [Android log buffer](Buffer) records are written to. Defaults to [`Buffer::Default`].
+    pub fn with_buffer(self, buffer: Buffer) -> Self {
+        Self { buffer, ..self }
+    }
+
+    /// Sets the [`Backend`] records are written to. Defaults to [`DefaultBackend`].
+    pub fn with_backend<B2>(self, backend: B2) -> AndroidLayerBuilder<E, B2, F> {
+        AndroidLayerBuilder {
+            tag: self.tag,
+            tag_strategy: self.tag_strategy,
+            buffer: self.buffer,
+            backend,
+            priority_mapping: self.priority_mapping,
+            nul_policy: self.nul_policy,
+            chunk_markers: self.chunk_markers,
+            max_len: self.max_len,
+            overflow: self.overflow,
+            location: self.location,
+            fatal_policy: self.fatal_policy,
+            format: self.format,
+            filter: self.filter,
+            filter_set: self.filter_set,
+            minimum_priority_sync: self.minimum_priority_sync,
+        }
+    }
+
+    /// Sets the function mapping the metadata of each event to the [`Priority`] of its records.
+    ///
+    /// See [`AndroidLogMakeWriter::with_priority_mapping`].
+    pub fn with_priority_mapping<M>(self, mapping: M) -> Self
+    where
+        M: Fn(&Metadata<'_>) -> Option<Priority> + Send + Sync + 'static,
+    {
+        Self {
+            priority_mapping: PriorityMapping::new(mapping),
+            ..self
+        }
+    }
+
+    /// Sets how interior NUL bytes in messages are handled. Defaults to [`NulPolicy::Escape`].
+    pub fn with_nul_policy(self, nul_policy: NulPolicy) -> Self {
+        Self { nul_policy, ..self }
+    }
+
+    /// Sets whether messages split into several records are marked as such. Defaults to `false`.
+    ///
+    /// See [`AndroidLogMakeWriter::with_chunk_markers`].
+    pub fn with_chunk_markers(self, chunk_markers: bool) -> Self {
+        Self {
+            chunk_markers,
+            ..self
+        }
+    }
+
+    /// Sets the maximum length of the message of a single record, in bytes.
+    ///
+    /// Defaults to the exact limit enforced by logd, which depends on the length of the tag.
+    pub fn with_max_len(self, max_len: usize) -> Self {
+        Self {
+            max_len: Some(max_len),
+            ..self
+        }
+    }
+
+    /// Sets what to do with messages longer than the [maximum length](Self::with_max_len).
+    /// Defaults to [`Overflow::Split`].
+    pub fn with_overflow(self, overflow: Overflow) -> Self {
+        Self { overflow, ..self }
+    }
+
+    /// Sets whether the source file and line of events are attached to their records. Defaults to `true`.
+    pub fn with_location(self, location: bool) -> Self {
+        Self { location, ..self }
+    }
+
+    /// Sets what to do with records at the [fatal](Priority::Fatal) priority. Defaults to [`FatalPolicy::Log`].
+    pub fn with_fatal_policy(self, fatal_policy: FatalPolicy) -> Self {
+        Self {
+            fatal_policy,
+            ..self
+        }
+    }
+
+    /// Sets the event formatter.
+    ///
+    /// Defaults to the [full](Full) format, without level nor timestamp since Android logs already record them.
+    pub fn with_format<E2>(self, format: Format<E2, ()>) -> AndroidLayerBuilder<E2, B, F> {
+        AndroidLayerBuilder {
+            tag: self.tag,
+            tag_strategy: self.tag_strategy,
+            buffer: self.buffer,
+            backend: self.backend,
+            priority_mapping: self.priority_mapping,
+            nul_policy: self.nul_policy,
+            chunk_markers: self.chunk_markers,
+            max_len: self.max_len,
+            overflow: self.overflow,
+            location: self.location,
+            fatal_policy: self.fatal_policy,
+            format,
+            filter: self.filter,
+            filter_set: self.filter_set,
+            minimum_priority_sync: self.minimum_priority_sync,
+        }
+    }
+
+    /// Uses the [compact](Compact) event formatter, keeping the other options of the current one.
+    pub fn compact(self) -> AndroidLayerBuilder<Compact, B, F>
+    where
+        E: Clone,
+    {
+        let format = self.format.clone().compact();
+        self.with_format(format)
+    }
+
+    /// Sets the [per-layer filter](tracing_subscriber::layer#per-layer-filtering) of the layer to an [`EnvFilter`]
+    /// parsed from the `RUST_LOG`-style directives held by the system property with the given name,
+    /// or from the given default directives if it isn't set.
+    ///
+    /// See [`property_env_filter`](crate::property_env_filter).
+    #[cfg(feature = "env-filter")]
+    pub fn with_env_filter_property(
+        self,
+        name: &str,
+        default: &str,
+    ) -> AndroidLayerBuilder<E, B, EnvFilter> {
+        let filter = crate::property_env_filter(&*system_properties(), name, default);
+        self.with_filter(filter)
+    }
+
+    /// Sets the [per-layer filter](tracing_subscriber::layer#per-layer-filtering) of the layer.
+    /// Defaults to [`LevelFilter::TRACE`], which enables everything.
+    pub fn with_filter<F2>(self, filter: F2) -> AndroidLayerBuilder<E, B, F2> {
+        AndroidLayerBuilder {
+            tag: self.tag,
+            tag_strategy: self.tag_strategy,
+            buffer: self.buffer,
+            backend: self.backend,
+            priority_mapping: self.priority_mapping,
+            nul_policy: self.nul_policy,
+            chunk_markers: self.chunk_markers,
+            max_len: self.max_len,
+            overflow: self.overflow,
+            location: self.location,
+            fatal_policy: self.fatal_policy,
+            format: self.format,
+            filter,
+            filter_set: true,
+            minimum_priority_sync: self.minimum_priority_sync,
+        }
+    }
+
+    /// Keeps the minimum priority of the backend, which also applies to native code logging through `liblog`,
+    /// in sync with the filter of the layer.
+    ///
+    /// The maximum level of the filter is set as the minimum priority of the backend when the layer is built,
+    /// and again whenever `tracing` rebuilds its filters, like after a [reload](tracing_subscriber::reload)
+    /// or a [poll](crate::PropertyWatcher::poll) finding changes.
+    /// If no [filter](Self::with_filter) is set, the minimum priority of the backend is used as the level filter instead.
+    /// This doesn't depend on the order in which the options are set.
+    ///
+    /// Backends which don't support minimum priorities, like `liblog` without the `api-30` feature, are left untouched.
+    ///
+    /// ```rust
+    /// use paranoid_android::{AndroidLayerBuilder, Backend, Memory, Priority};
+    /// use tracing_subscriber::{filter::LevelFilter, prelude::*, reload};
+    ///
+    /// let memory = Memory::new();
+    /// memory.set_minimum_priority(Priority::Warn).unwrap();
+    /// let _layer = AndroidLayerBuilder::new("app")
+    ///     .with_minimum_priority_sync()
+    ///     .with_backend(memory.clone())
+    ///     .build::<tracing_subscriber::Registry>();
+    /// assert_eq!(memory.minimum_priority(), Some(Priority::Warn));
+    ///
+    /// let (filter, handle) = reload::Layer::new(LevelFilter::DEBUG);
+    /// let layer = AndroidLayerBuilder::new("app")
+    ///     .with_minimum_priority_sync()
+    ///     .with_backend(memory.clone())
+    ///     .with_filter(filter)
+    ///     .build();
+    /// assert_eq!(memory.minimum_priority(), Some(Priority::Debug));
+    ///
+    /// tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+    ///     handle.reload(LevelFilter::ERROR).unwrap();
+    ///     assert_eq!(memory.minimum_priority(), Some(Priority::Error));
+    /// });
+    /// ```
+    pub fn with_minimum_priority_sync(self) -> Self {
+        Self {
+            minimum_priority_sync: true,
+            ..self
+        }
+    }
+}
+
+impl<E, B, F> AndroidLayerBuilder<E, B, F>
+where
+    B: Backend + 'static,
+{
+    /// Builds the layer, which can be [composed](tracing_subscriber::Layer) with other layers to construct a [`Subscriber`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains an interior NUL byte. See [`try_build`](Self::try_build) for a fallible version.
+    pub fn build<S>(self) -> FilteredLayer<S, E, B, F>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        Format<E, ()>: FormatEvent<S, ReservedFields> + 'static,
+        F: Filter<S> + 'static,
+    {
+        self.try_build().unwrap()
+    }
+
+    /// Builds the layer, which can be [composed](tracing_subscriber::Layer) with other layers to construct a [`Subscriber`],
+    /// or returns an error if the tag contains an interior NUL byte.
+    pub fn try_build<S>(self) -> Result<FilteredLayer<S, E, B, F>, Error>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        Format<E, ()>: FormatEvent<S, ReservedFields> + 'static,
+        F: Filter<S> + 'static,
+    {
+        let level = match self.minimum_priority_sync && !self.filter_set {
+            true => minimum_level(&self.backend),
+            false => LevelFilter::TRACE,
+        };
+
+        let mut make_writer =
+            AndroidLogMakeWriter::try_with_backend(self.tag, self.buffer, self.backend)?
+                .with_tag_strategy(self.tag_strategy)
+                .with_mapping(self.priority_mapping)
+                .with_nul_policy(self.nul_policy)
+                .with_chunk_markers(self.chunk_markers)
+                .with_overflow(self.overflow)
+                .with_location(self.location)
+                .with_fatal_policy(self.fatal_policy);
+        if let Some(max_len) = self.max_len {
+            make_writer = make_writer.with_max_len(max_len);
+        }
+
+        let layer = fmt::Layer::new()
+            .fmt_fields(ReservedFields::default())
+            .event_format(self.format)
+            .with_writer(make_writer);
+        let filter = SyncedFilter {
+            filter: self.filter,
+            level,
+            backend: self
+                .minimum_priority_sync
+                .then(|| layer.writer().backend().clone()),
+        };
+        if let Some(backend) = &filter.backend {
+            let level = Filter::<S>::max_level_hint(&filter).unwrap_or(LevelFilter::TRACE);
+            match backend.set_minimum_priority(Priority::from_level_filter(level)) {
+                Err(e) if e.kind() != io::ErrorKind::Unsupported => return Err(Error::Setup(e)),
+                _ => {}
+            }
+        }
+        Ok(layer.with_filter(filter))
+    }
+
+    /// Builds the layer and attempts to set a [`Subscriber`] using it as the
+    /// [global default subscriber] in the current scope, panicking if this fails.
+    ///
+    /// See [`try_init`](Self::try_init) for a version that never panics.
+    ///
+    /// [global default subscriber]: https://docs.rs/tracing/0.1/tracing/dispatcher/index.html#setting-the-default-subscriber
+    pub fn init(self)
+    where
+        B: Send + Sync,
+        Format<E, ()>: FormatEvent<Registry, ReservedFields> + Send + Sync + 'static,
+        F: Filter<Registry> + Send + Sync + 'static,
+    {
+        self.try_init().unwrap()
+    }
+
+    /// Builds the layer and attempts to set a [`Subscriber`] using it as the
+    /// [global default subscriber] in the current scope.
+    ///
+    /// Returns an error if the tag contains an interior NUL byte or if a global default subscriber has already been set.
+    ///
+    /// [global default subscriber]: https://docs.rs/tracing/0.1/tracing/dispatcher/index.html#setting-the-default-subscriber
+    pub fn try_init(self) -> Result<(), Error>
+    where
+        B: Send + Sync,
+        Format<E, ()>: FormatEvent<Registry, ReservedFields> + Send + Sync + 'static,
+        F: Filter<Registry> + Send + Sync + 'static,
+    {
+        Registry::default().with(self.try_build()?).try_init()?;
+        Ok(())
+    }
+}
+
+impl<F, B> SyncedFilter<F, B> {
+    /// Returns the wrapped filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+}
+
+impl<S, F, B> Filter<S> for SyncedFilter<F, B>
+where
+    F: Filter<S>,
+    B: Backend,
+{
+    fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        *meta.level() <= self.level && self.filter.enabled(meta, cx)
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        match *meta.level() <= self.level {
+            true => self.filter.callsite_enabled(meta),
+            false => Interest::never(),
+        }
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, cx: &Context<'_, S>) -> bool {
+        self.filter.event_enabled(event, cx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        let level = match self.filter.max_level_hint() {
+            Some(level) => level.min(self.level),
+            None => self.level,
+        };
+        if let Some(backend) = &self.backend {
+            // called whenever the filters are rebuilt, which can't report errors
+            let _ = backend.set_minimum_priority(Priority::from_level_filter(level));
+        }
+        Some(level)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.on_new_span(attrs, id, ctx)
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.filter.on_record(id, values, ctx)
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.on_enter(id, ctx)
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.on_exit(id, ctx)
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.filter.on_close(id, ctx)
+    }
+}
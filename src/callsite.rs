@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use tracing_core::{
+    callsite::{self, Callsite, Identifier},
+    dispatcher,
+    field::{FieldSet, Value},
+    metadata::Kind,
+    subscriber::Interest,
+    Event, Level, Metadata,
+};
+
+use crate::sync::lock;
+
+/// A callsite created at runtime for records which don't originate from `tracing`,
+/// like the records of native libraries.
+///
+/// Callsites are leaked, but there is at most one per distinct target, level and location,
+/// and no more than [`MAX_CALLSITES`] of them plus one per level:
+/// beyond that, records share a callsite per level with the [`FALLBACK_TARGET`] and no location.
+pub(crate) struct DynamicCallsite {
+    metadata: OnceLock<Metadata<'static>>,
+    interest: AtomicU8,
+}
+
+type Key = (String, Level, Option<String>, Option<u32>);
+
+static CALLSITES: OnceLock<Mutex<HashMap<Key, &'static DynamicCallsite>>> = OnceLock::new();
+
+/// The number of distinct callsites created before records fall back to a shared callsite per level.
+const MAX_CALLSITES: usize = 1024;
+
+/// The target of the callsites shared by records once there are [`MAX_CALLSITES`] callsites.
+const FALLBACK_TARGET: &str = "native";
+
+const NEVER: u8 = 0;
+const SOMETIMES: u8 = 1;
+const ALWAYS: u8 = 2;
+
+impl DynamicCallsite {
+    /// Returns the callsite with the given target, level and location, registering it if needed.
+    pub(crate) fn get(
+        target: &str,
+        level: Level,
+        file: Option<&str>,
+        line: Option<u32>,
+    ) -> &'static Self {
+        let mut key = (target.to_owned(), level, file.map(str::to_owned), line);
+        let mut callsites = lock(CALLSITES.get_or_init(Default::default));
+        if let Some(callsite) = callsites.get(&key) {
+            return callsite;
+        }
+        if callsites.len() >= MAX_CALLSITES {
+            key = (FALLBACK_TARGET.to_owned(), level, None, None);
+            if let Some(callsite) = callsites.get(&key) {
+                return callsite;
+            }
+        }
+
+        let callsite: &'static Self = Box::leak(Box::new(Self {
+            metadata: OnceLock::new(),
+            interest: AtomicU8::new(SOMETIMES),
+        }));
+        let (target, _, file, line) = &key;
+        let _ = callsite.metadata.set(Metadata::new(
+            "native",
+            leak(target),
+            level,
+            file.as_deref().map(leak),
+            *line,
+            None,
+            FieldSet::new(&["message"], Identifier(callsite)),
+            Kind::EVENT,
+        ));
+        callsites.insert(key, callsite);
+        drop(callsites);
+
+        // registering calls into the subscribers, which may log and need the lock again;
+        // until then, the callsite is only dispatched to if the current subscriber enables it
+        callsite::register(callsite);
+        callsite
+    }
+
+    /// Dispatches an event with the given message from this callsite, if the current subscriber is interested in it.
+    pub(crate) fn dispatch(&'static self, message: &str) {
+        if self.interest.load(Ordering::Relaxed) == NEVER {
+            return;
+        }
+
+        let metadata = Callsite::metadata(self);
+        dispatcher::get_default(|dispatch| {
+            if !dispatch.enabled(metadata) {
+                return;
+            }
+
+            let fields = metadata.fields();
+            if let Some(field) = fields.field("message") {
+                let values = [(&field, Some(&message as &dyn Value))];
+                dispatch.event(&Event::new(metadata, &fields.value_set(&values)));
+            }
+        })
+    }
+}
+
+impl Callsite for DynamicCallsite {
+    fn set_interest(&self, interest: Interest) {
+        let interest = if interest.is_never() {
+            NEVER
+        } else if interest.is_always() {
+            ALWAYS
+        } else {
+            SOMETIMES
+        };
+        self.interest.store(interest, Ordering::Relaxed);
+    }
+
+    fn metadata(&self) -> &Metadata<'_> {
+        self.metadata
+            .get()
+            .expect("callsites are registered after their metadata is set")
+    }
+}
+
+fn leak(s: &str) -> &'static str {
+    Box::leak(s.to_owned().into_boxed_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callsites_fall_back_to_a_shared_one_per_level() {
+        for i in 0..MAX_CALLSITES {
+            DynamicCallsite::get("target", Level::INFO, Some("file.rs"), Some(i as u32 + 1));
+        }
+
+        let callsite = DynamicCallsite::get("other", Level::WARN, Some("file.rs"), Some(1));
+        let metadata = Callsite::metadata(callsite);
+        assert_eq!(metadata.target(), FALLBACK_TARGET);
+        assert_eq!(*metadata.level(), Level::WARN);
+        assert_eq!(metadata.file(), None);
+
+        let other = DynamicCallsite::get("another", Level::WARN, None, None);
+        assert!(std::ptr::eq(callsite, other));
+
+        let existing = DynamicCallsite::get("target", Level::INFO, Some("file.rs"), Some(1));
+        assert_eq!(Callsite::metadata(existing).target(), "target");
+    }
+}
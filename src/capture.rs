@@ -0,0 +1,73 @@
+use std::{cell::Cell, ffi::CStr, os::raw::c_char, panic};
+
+use ndk_sys::{__android_log_logd_logger, __android_log_message, __android_log_set_logger};
+use tracing_core::{callsite::Identifier, Level, Metadata};
+
+use crate::{backend::is_writing, callsite::DynamicCallsite, logging::Priority};
+
+thread_local! {
+    /// The callsite of the record being captured on this thread, if any.
+    static CAPTURING: Cell<Option<&'static DynamicCallsite>> = const { Cell::new(None) };
+}
+
+/// Captures the records other native libraries log through `liblog` as `tracing` events.
+///
+/// Each record becomes an event whose target is the tag of the record, whose level matches its priority
+/// and whose location is the file and line of the record, if any.
+/// Every record is still forwarded to logd with its own tag, through `__android_log_logd_logger`.
+/// The records written by this crate are never captured again,
+/// and the captured events themselves aren't written by the writers of this crate, since their record already was.
+///
+/// This replaces the logger of `liblog` for the whole process using `__android_log_set_logger`.
+/// Records are forwarded to the default logger writing to logd, not to any logger installed before.
+pub fn capture_liblog() {
+    unsafe { __android_log_set_logger(Some(logger)) };
+}
+
+unsafe extern "C" fn logger(message: *const __android_log_message) {
+    if message.is_null() {
+        return;
+    }
+
+    __android_log_logd_logger(message);
+
+    // records written by this crate, or logged while capturing another one, are never captured
+    let capturing = CAPTURING.try_with(|c| c.get().is_some());
+    if !is_writing() && matches!(capturing, Ok(false)) {
+        let _ = panic::catch_unwind(|| capture(&*message));
+        let _ = CAPTURING.try_with(|c| c.set(None));
+    }
+}
+
+/// Returns whether an event with the given metadata is a record being captured on this thread.
+pub(crate) fn is_captured(meta: &Metadata<'_>) -> bool {
+    CAPTURING
+        .try_with(|c| {
+            c.get()
+                .map_or(false, |callsite| meta.callsite() == Identifier(callsite))
+        })
+        .unwrap_or(false)
+}
+
+/// Dispatches the given record as an event, marking its callsite as being captured.
+unsafe fn capture(message: &__android_log_message) {
+    let text = match to_str(message.message) {
+        Some(text) => text,
+        None => return,
+    };
+    let tag = to_str(message.tag).unwrap_or("native");
+    let level = Priority::from_raw(message.priority).map_or(Level::INFO, Level::from);
+    let file = to_str(message.file);
+    let line = Some(message.line).filter(|&line| line != 0);
+
+    let callsite = DynamicCallsite::get(tag, level, file, line);
+    let _ = CAPTURING.try_with(|c| c.set(Some(callsite)));
+    callsite.dispatch(text);
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
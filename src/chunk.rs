@@ -0,0 +1,286 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+/// An iterator over the chunks of a message, as returned by [`chunks`].
+#[derive(Debug, Clone)]
+pub struct Chunks<'a> {
+    rest: &'a [u8],
+    max_len: usize,
+}
+
+/// Splits a message into chunks of at most `max_len` bytes.
+///
+/// Chunks end on line boundaries whenever possible, keeping the newline at the end of the chunk.
+/// A single line longer than `max_len` is split as late as possible, but never inside a UTF-8 sequence,
+/// unless `max_len` is smaller than the sequence itself.
+/// Concatenating the chunks always yields the original message.
+///
+/// ```rust
+/// use paranoid_android::chunks;
+///
+/// fn split(message: &str, max_len: usize) -> Vec<&str> {
+///     chunks(message.as_bytes(), max_len)
+///         .map(|c| std::str::from_utf8(c).unwrap())
+///         .collect()
+/// }
+///
+/// assert_eq!(split("short", 8), ["short"]);
+/// assert_eq!(split("one\ntwo\nthree", 8), ["one\ntwo\n", "three"]);
+/// assert_eq!(split("overlong line\nok", 8), ["overlong", " line\nok"]);
+/// assert_eq!(split("ééééé", 5), ["éé", "éé", "é"]);
+/// assert_eq!(split("a🦀🦀", 4), ["a", "🦀", "🦀"]);
+/// ```
+pub fn chunks(message: &[u8], max_len: usize) -> Chunks<'_> {
+    Chunks {
+        rest: message,
+        max_len: max_len.max(1),
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        if self.rest.len() <= self.max_len {
+            return Some(std::mem::take(&mut self.rest));
+        }
+
+        let len = match self.rest[..self.max_len].iter().rposition(|&b| b == b'\n') {
+            Some(newline) => newline + 1,
+            None => utf8_boundary(self.rest, self.max_len),
+        };
+
+        let (chunk, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(chunk)
+    }
+}
+
+/// Truncates a message to at most `max_len` bytes, without cutting a UTF-8 sequence in half.
+pub(crate) fn truncate(message: &[u8], max_len: usize) -> &[u8] {
+    match message.len() > max_len {
+        true => &message[..utf8_boundary(message, max_len)],
+        false => message,
+    }
+}
+
+/// Returns the last UTF-8 character boundary at or before `max_len`,
+/// or `max_len` itself if there is none.
+fn utf8_boundary(data: &[u8], max_len: usize) -> usize {
+    // UTF-8 sequences are at most 4 bytes long, so there's at most 3 continuation bytes to skip
+    (max_len.saturating_sub(3)..=max_len)
+        .rev()
+        .find(|&i| i > 0 && !is_continuation(data[i]))
+        .unwrap_or(max_len)
+}
+
+fn is_continuation(b: u8) -> bool {
+    b & 0b1100_0000 == 0b1000_0000
+}
+
+/// Stitches messages split into several records with [chunk markers](crate::AndroidLogMakeWriter::with_chunk_markers)
+/// back together.
+///
+/// Takes the messages of parsed logcat lines, in order, and returns whole messages in the order they were completed.
+/// Messages without markers are returned as is, and incomplete messages are returned last, with their missing parts omitted.
+/// Since chunk IDs are only unique for a given writer, the lines should be filtered by process and tag beforehand.
+///
+/// ```rust
+/// let lines = [
+///     "[1/2 #a] first ",
+///     "[1/3 #b] second ",
+///     "unrelated",
+///     "[2/2 #a] message",
+///     "[3/3 #b] message",
+///     "[2/3 #b] split ",
+/// ];
+///
+/// assert_eq!(
+///     paranoid_android::reassemble(lines),
+///     ["unrelated", "first message", "second split message"],
+/// );
+/// ```
+pub fn reassemble<I>(messages: I) -> Vec<String>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut reassembled = Vec::new();
+    let mut pending: HashMap<u32, Pending> = HashMap::new();
+    let mut pending_order = Vec::new();
+
+    for message in messages {
+        let message = message.as_ref();
+        let (marker, rest) = match Marker::parse(message) {
+            Some(parsed) => parsed,
+            None => {
+                reassembled.push(message.to_owned());
+                continue;
+            }
+        };
+
+        // the count comes from untrusted text, so parts are only allocated as they show up
+        let parts = pending.entry(marker.id).or_insert_with(|| {
+            pending_order.push(marker.id);
+            Pending {
+                count: marker.count,
+                parts: BTreeMap::new(),
+            }
+        });
+        if marker.count == parts.count {
+            parts.parts.insert(marker.index, rest.to_owned());
+        }
+
+        if parts.parts.len() == parts.count {
+            let parts = pending.remove(&marker.id).unwrap_or_default();
+            reassembled.push(parts.parts.into_values().collect());
+        }
+    }
+
+    for id in pending_order {
+        if let Some(parts) = pending.remove(&id) {
+            reassembled.push(parts.parts.into_values().collect());
+        }
+    }
+    reassembled
+}
+
+/// The parts of a message received so far, by index.
+#[derive(Default)]
+struct Pending {
+    count: usize,
+    parts: BTreeMap<usize, String>,
+}
+
+/// A marker prefixed to each record of a message split into several records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Marker {
+    pub(crate) index: usize,
+    pub(crate) count: usize,
+    pub(crate) id: u32,
+}
+
+impl Marker {
+    /// Parses a marker at the start of a message, returning it along with the rest of the message.
+    fn parse(message: &str) -> Option<(Self, &str)> {
+        let (marker, rest) = message.strip_prefix('[')?.split_once("] ")?;
+        let (position, id) = marker.split_once(" #")?;
+        let (index, count) = position.split_once('/')?;
+
+        let marker = Marker {
+            index: index.parse().ok()?,
+            count: count.parse().ok()?,
+            id: u32::from_str_radix(id, 16).ok()?,
+        };
+        if marker.index == 0 || marker.index > marker.count {
+            return None;
+        }
+        Some((marker, rest))
+    }
+}
+
+impl Marker {
+    /// Returns the length of this marker once formatted.
+    pub(crate) fn len(&self) -> usize {
+        let decimal = |n: usize| n.checked_ilog10().unwrap_or(0) as usize + 1;
+        let hex = self.id.checked_ilog(16).unwrap_or(0) as usize + 1;
+        // `[` + index + `/` + count + ` #` + id + `] `
+        1 + decimal(self.index) + 1 + decimal(self.count) + 2 + hex + 2
+    }
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}/{} #{:x}] ", self.index, self.count, self.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "a\né\n\n🦀🦀 long line with ü and ∑\nx\n€€€\n";
+
+    #[test]
+    fn chunks_concatenate_to_message() {
+        for max_len in 1..=MESSAGE.len() + 1 {
+            let chunks: Vec<_> = chunks(MESSAGE.as_bytes(), max_len).collect();
+            assert_eq!(chunks.concat(), MESSAGE.as_bytes(), "max_len {}", max_len);
+            assert!(chunks.iter().all(|c| !c.is_empty() && c.len() <= max_len));
+        }
+    }
+
+    #[test]
+    fn chunks_end_on_utf8_boundaries() {
+        // the widest character is 4 bytes long, so any smaller limit has to split it
+        for max_len in 4..=MESSAGE.len() + 1 {
+            for chunk in chunks(MESSAGE.as_bytes(), max_len) {
+                assert!(
+                    std::str::from_utf8(chunk).is_ok(),
+                    "max_len {}: {:?}",
+                    max_len,
+                    chunk
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_split_lines_only_when_needed() {
+        for max_len in 4..=MESSAGE.len() + 1 {
+            let mut offset = 0;
+            for chunk in chunks(MESSAGE.as_bytes(), max_len) {
+                let chunk = std::str::from_utf8(chunk).unwrap();
+                offset += chunk.len();
+                let rest = &MESSAGE[offset..];
+                if rest.is_empty() {
+                    break;
+                }
+
+                if chunk.ends_with('\n') {
+                    // the next line doesn't fit in the rest of the chunk
+                    let line_len = rest.find('\n').map_or(rest.len(), |i| i + 1);
+                    assert!(
+                        chunk.len() + line_len > max_len,
+                        "max_len {}: {:?}",
+                        max_len,
+                        chunk
+                    );
+                } else {
+                    // a line too long for a chunk is split as late as possible
+                    assert!(!chunk.contains('\n'), "max_len {}: {:?}", max_len, chunk);
+                    let next_char = rest.chars().next().unwrap().len_utf8();
+                    assert!(
+                        chunk.len() + next_char > max_len,
+                        "max_len {}: {:?}",
+                        max_len,
+                        chunk
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reassemble_ignores_huge_counts() {
+        let lines = ["[1/4000000000 #1] x", "[2/4000000000 #1] y"];
+        assert_eq!(reassemble(lines), ["xy"]);
+    }
+
+    #[test]
+    fn reassemble_ignores_inconsistent_counts() {
+        let lines = ["[1/2 #1] x", "[2/3 #1] y", "[2/2 #1] z"];
+        assert_eq!(reassemble(lines), ["xz"]);
+    }
+
+    #[test]
+    fn chunks_keep_newlines_at_the_end() {
+        let chunks: Vec<_> = chunks(b"one\ntwo\nthree\n", 8).collect();
+        assert_eq!(chunks, [&b"one\ntwo\n"[..], b"three\n"]);
+    }
+}
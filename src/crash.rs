@@ -0,0 +1,242 @@
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    ffi::CString,
+    fmt::Write as _,
+    io::Write as _,
+    panic::{self, PanicHookInfo},
+    thread,
+};
+
+use tracing_core::dispatcher;
+use tracing_subscriber::{registry::LookupSpan, Registry};
+
+use crate::{
+    backend::{Backend, DefaultBackend},
+    chunk::truncate,
+    logging::{Buffer, Priority},
+    recorder::dump_recent,
+    AndroidLogMakeWriter, Error, FatalPolicy,
+};
+
+/// A panic hook writing crash reports to the [crash buffer](Buffer::Crash) at the [fatal](Priority::Fatal) priority,
+/// falling back to the [main buffer](Buffer::Main) if the crash buffer can't be written to.
+///
+/// The fallback depends on the [`Backend`] reporting failures:
+/// with the `api-30` feature, `liblog` doesn't report them, so reports written through it never fall back,
+/// even when the crash buffer is unavailable to the app.
+///
+/// Reports hold the panic message, its location, the name of the panicking thread,
+/// the stack of spans entered when it panicked and optionally a backtrace.
+/// The events kept by [`FlightRecorder`](crate::FlightRecorder)s are written out before the report,
+/// and once the report is written, the previous panic hook is called.
+///
+/// ```rust
+/// use std::panic;
+///
+/// use paranoid_android::{Buffer, Memory, PanicHook, Priority};
+/// use tracing_subscriber::prelude::*;
+///
+/// let memory = Memory::new();
+/// PanicHook::with_backend("app", memory.clone()).install();
+///
+/// let subscriber = tracing_subscriber::registry();
+/// tracing::subscriber::with_default(subscriber, || {
+///     let _span = tracing::info_span!("request").entered();
+///     let _ = panic::catch_unwind(|| panic!("boom"));
+/// });
+///
+/// let records = memory.take();
+/// assert_eq!(records.len(), 1);
+/// assert_eq!((records[0].buffer, records[0].priority), (Buffer::Crash, Priority::Fatal));
+/// assert!(records[0].message.contains("panicked at src/"));
+/// assert!(records[0].message.contains("boom"));
+/// assert!(records[0].message.contains("in span: request"));
+/// ```
+#[derive(Debug)]
+pub struct PanicHook<B: Backend = DefaultBackend> {
+    make_writer: AndroidLogMakeWriter<B>,
+    backtrace: bool,
+}
+
+/// The maximum length of an abort message, in bytes.
+const ABORT_MESSAGE_MAX_LEN: usize = 1024;
+
+/// Installs a [`PanicHook`] writing crash reports with the given tag.
+///
+/// # Panics
+///
+/// Panics if the tag contains an interior NUL byte.
+pub fn install_panic_hook(tag: impl ToString) {
+    PanicHook::new(tag).install()
+}
+
+impl PanicHook {
+    /// Returns a new [`PanicHook`] writing crash reports with the given tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains an interior NUL byte. See [`try_new`](Self::try_new) for a fallible version.
+    pub fn new(tag: impl ToString) -> Self {
+        Self::with_backend(tag, Default::default())
+    }
+
+    /// Returns a new [`PanicHook`] writing crash reports with the given tag,
+    /// or an error if the tag contains an interior NUL byte.
+    pub fn try_new(tag: impl ToString) -> Result<Self, Error> {
+        Self::try_with_backend(tag, Default::default())
+    }
+}
+
+impl<B: Backend> PanicHook<B> {
+    /// Returns a new [`PanicHook`] writing crash reports with the given tag to the given [`Backend`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains an interior NUL byte. See [`try_with_backend`](Self::try_with_backend) for a fallible version.
+    pub fn with_backend(tag: impl ToString, backend: B) -> Self {
+        Self::try_with_backend(tag, backend).unwrap()
+    }
+
+    /// Returns a new [`PanicHook`] writing crash reports with the given tag to the given [`Backend`],
+    /// or an error if the tag contains an interior NUL byte.
+    pub fn try_with_backend(tag: impl ToString, backend: B) -> Result<Self, Error> {
+        Ok(Self {
+            make_writer: AndroidLogMakeWriter::try_with_backend(
+                tag.to_string(),
+                Buffer::Crash,
+                backend,
+            )?,
+            backtrace: false,
+        })
+    }
+
+    /// Sets whether crash reports include a backtrace of the panicking thread. Defaults to `false`.
+    ///
+    /// Backtraces are captured regardless of the `RUST_BACKTRACE` environment variable.
+    pub fn with_backtrace(self, backtrace: bool) -> Self {
+        Self { backtrace, ..self }
+    }
+
+    /// Sets whether crash reports are also set as the [abort message](Backend::set_abort_message) of the process. Defaults to `false`.
+    ///
+    /// The abort message is included in the tombstone written when the process aborts,
+    /// and shows up in crash reports collected by app stores.
+    ///
+    /// ```rust
+    /// use std::panic;
+    ///
+    /// use paranoid_android::{Memory, PanicHook};
+    ///
+    /// let memory = Memory::new();
+    /// PanicHook::with_backend("app", memory.clone())
+    ///     .with_abort_message(true)
+    ///     .install();
+    ///
+    /// let _ = panic::catch_unwind(|| panic!("boom"));
+    /// let message = memory.abort_message().unwrap();
+    /// assert!(message.starts_with("panicked at src/"));
+    /// assert!(message.ends_with("boom"));
+    /// ```
+    pub fn with_abort_message(self, abort_message: bool) -> Self {
+        let fatal_policy = match abort_message {
+            true => FatalPolicy::SetAbortMessage,
+            false => FatalPolicy::Log,
+        };
+        Self {
+            make_writer: self.make_writer.with_fatal_policy(fatal_policy),
+            ..self
+        }
+    }
+
+    /// Sets the panic hook, chaining to the previous one.
+    pub fn install(self)
+    where
+        B: Send + Sync + 'static,
+    {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            dump_recent();
+            self.write(&self.report(info));
+            previous(info);
+        }));
+    }
+
+    /// Returns the crash report of the given panic.
+    fn report(&self, info: &PanicHookInfo<'_>) -> String {
+        let mut report = String::from("panicked");
+        if let Some(location) = info.location() {
+            let _ = write!(report, " at {}", location);
+        }
+        let thread = thread::current();
+        let _ = writeln!(
+            report,
+            " on thread '{}':",
+            thread.name().unwrap_or("<unnamed>")
+        );
+        report.push_str(payload(info.payload()));
+
+        let spans = span_stack();
+        if !spans.is_empty() {
+            let _ = write!(report, "\nin span: {}", spans.join(" > "));
+        }
+
+        if self.backtrace {
+            let _ = write!(report, "\nbacktrace:\n{}", Backtrace::force_capture());
+        }
+        report
+    }
+
+    /// Writes the given crash report, falling back to the main buffer if the crash buffer fails.
+    fn write(&self, report: &str) {
+        for buffer in [Buffer::Crash, Buffer::Main] {
+            let mut writer = self.make_writer.writer(buffer, Priority::Fatal);
+            if writer
+                .write_all(report.as_bytes())
+                .and_then(|_| writer.flush())
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+}
+
+/// Returns the message of a panic, if it has one.
+fn payload(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Returns the names of the spans entered on the current thread, from the outermost to the innermost.
+///
+/// Spans are only known if the current subscriber is built on a [`Registry`].
+fn span_stack() -> Vec<&'static str> {
+    dispatcher::get_default(|dispatch| {
+        let registry = match dispatch.downcast_ref::<Registry>() {
+            Some(registry) => registry,
+            None => return Vec::new(),
+        };
+        let span = match dispatch
+            .current_span()
+            .id()
+            .and_then(|id| registry.span(id))
+        {
+            Some(span) => span,
+            None => return Vec::new(),
+        };
+        span.scope().from_root().map(|span| span.name()).collect()
+    })
+}
+
+/// Returns the abort message holding the given message, truncated and without NUL bytes.
+pub(crate) fn abort_message(message: &[u8]) -> CString {
+    let message = truncate(message.trim_ascii_end(), ABORT_MESSAGE_MAX_LEN);
+    let message: Vec<u8> = message.iter().copied().filter(|&b| b != 0).collect();
+    CString::new(message).unwrap_or_default()
+}
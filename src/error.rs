@@ -0,0 +1,83 @@
+use std::{error, ffi::NulError, fmt, io};
+
+use tracing_subscriber::util::TryInitError;
+
+/// The error type of this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The tag contains an interior NUL byte.
+    InvalidTag(NulError),
+    /// No message buffer could be allocated from the pool.
+    PoolExhausted,
+    /// A global default subscriber has already been set.
+    AlreadyInitialized(TryInitError),
+    /// A system call failed while setting up logging, like connecting a socket or installing a signal handler.
+    Setup(io::Error),
+}
+
+/// An error returned when parsing a [`Priority`](crate::Priority) or a [`Buffer`](crate::Buffer) fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    kind: &'static str,
+}
+
+impl ParseError {
+    pub(crate) fn new(kind: &'static str) -> Self {
+        Self { kind }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidTag(_) => f.write_str("tag contains an interior NUL byte"),
+            Error::PoolExhausted => f.write_str("message buffer pool is exhausted"),
+            Error::AlreadyInitialized(_) => {
+                f.write_str("a global default subscriber has already been set")
+            }
+            Error::Setup(_) => f.write_str("failed to set up logging"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::InvalidTag(e) => Some(e),
+            Error::PoolExhausted => None,
+            Error::AlreadyInitialized(e) => Some(e),
+            Error::Setup(e) => Some(e),
+        }
+    }
+}
+
+impl From<NulError> for Error {
+    fn from(e: NulError) -> Self {
+        Error::InvalidTag(e)
+    }
+}
+
+impl From<TryInitError> for Error {
+    fn from(e: TryInitError) -> Self {
+        Error::AlreadyInitialized(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Setup(e) => e,
+            Error::InvalidTag(_) => io::Error::new(io::ErrorKind::InvalidInput, e),
+            e => io::Error::other(e),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid Android log {}", self.kind)
+    }
+}
+
+impl error::Error for ParseError {}
@@ -0,0 +1,785 @@
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use tracing_core::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::layer::Context;
+
+use crate::{
+    backend::{Backend, DefaultBackend},
+    writer::ErrorHandler,
+    Counter,
+};
+
+/// A value in a binary [event log](https://source.android.com/docs/core/tests/debug/understanding-logging#event-log-tags) record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventValue {
+    /// A 32 bit integer.
+    Int(i32),
+    /// A 64 bit integer.
+    Long(i64),
+    /// A 32 bit float.
+    Float(f32),
+    /// A string.
+    String(String),
+    /// A list of values, holding at most 255 of them.
+    List(Vec<EventValue>),
+}
+
+/// The type of an event log field, as annotated in `event-log-tags` files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    /// A 32 bit integer.
+    Int = 1,
+    /// A 64 bit integer.
+    Long = 2,
+    /// A string.
+    String = 3,
+    /// A list of values.
+    List = 4,
+    /// A 32 bit float.
+    Float = 5,
+}
+
+/// A mapping from `tracing` event names to event log tag numbers and field types.
+///
+/// Event names can be set using the `name:` argument of the `tracing` macros.
+///
+/// The mapping can be written as an `event-log-tags` file, which tools like `logcat -b events`
+/// use to decode records, and read back from one to [decode](EventTags::decode) records.
+/// The `event-log-tags` binary shipped with this crate generates such a file
+/// from a list of event names and field types.
+///
+/// ```rust
+/// use paranoid_android::{EventTags, EventType, EventValue};
+///
+/// let tags = EventTags::new().with_fields(
+///     "battery_level",
+///     2722,
+///     [("level", EventType::Long), ("charging", EventType::Int)],
+/// );
+///
+/// let mut file = Vec::new();
+/// tags.write_event_log_tags(&mut file).unwrap();
+/// assert_eq!(file, b"2722 battery_level (level|2),(charging|1)\n");
+///
+/// let tags: EventTags = std::str::from_utf8(&file).unwrap().parse().unwrap();
+/// let mut payload = Vec::new();
+/// EventValue::List(vec![EventValue::Long(42), EventValue::Int(1)]).encode(&mut payload);
+///
+/// let event = tags.decode(2722, &payload).unwrap();
+/// assert_eq!(event.name, "battery_level");
+/// assert_eq!(
+///     event.fields,
+///     [
+///         ("level".to_owned(), EventValue::Long(42)),
+///         ("charging".to_owned(), EventValue::Int(1)),
+///     ],
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EventTags {
+    tags: HashMap<String, EventTag>,
+}
+
+/// An event log record decoded using [`EventTags`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedEvent {
+    /// The name of the event.
+    pub name: String,
+    /// The values of the event, along with their field names.
+    ///
+    /// Values without a field name in the `event-log-tags` file are named after their position.
+    pub fields: Vec<(String, EventValue)>,
+}
+
+#[derive(Debug, Clone)]
+struct EventTag {
+    tag: u32,
+    fields: Vec<(String, EventType)>,
+}
+
+/// A [`Layer`](tracing_subscriber::Layer) that writes `tracing` events as binary
+/// [event log](crate::Buffer::Events) records.
+///
+/// Only events whose name has a tag in the given [`EventTags`] are written.
+/// Their fields are written as a [list](EventValue::List): if the tag declares fields,
+/// with the values of these fields converted to their declared types, in that order,
+/// or else with the values of all the fields of the event, in declaration order.
+/// The `message` field is never written.
+///
+/// Events missing a declared field, or with a value that doesn't fit its declared type, aren't
+/// written. These failures, like those of the backend, are reported to the
+/// [error handler](Self::with_error_handler).
+///
+/// ```rust
+/// use paranoid_android::{EventLogLayer, EventTags, EventValue, Memory};
+/// use tracing_subscriber::prelude::*;
+///
+/// let memory = Memory::new();
+/// let tags = EventTags::new().with("battery_level", 2722);
+/// let subscriber = tracing_subscriber::registry()
+///     .with(EventLogLayer::with_backend(tags, memory.clone()));
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::info!(name: "battery_level", level = 42, charging = true);
+///     tracing::info!("not an event log record");
+/// });
+///
+/// let mut payload = Vec::new();
+/// EventValue::List(vec![EventValue::Long(42), EventValue::Int(1)]).encode(&mut payload);
+///
+/// let events = memory.take_events();
+/// assert_eq!(events.len(), 1);
+/// assert_eq!(events[0].tag, 2722);
+/// assert_eq!(events[0].payload, payload);
+/// ```
+#[derive(Debug)]
+pub struct EventLogLayer<B = DefaultBackend> {
+    tags: EventTags,
+    backend: B,
+    errors: ErrorHandler,
+}
+
+const TYPE_INT: u8 = 0;
+const TYPE_LONG: u8 = 1;
+const TYPE_STRING: u8 = 2;
+const TYPE_LIST: u8 = 3;
+const TYPE_FLOAT: u8 = 4;
+
+const MESSAGE_FIELD: &str = "message";
+
+impl EventValue {
+    /// Appends the binary encoding of this value to the given buffer.
+    ///
+    /// ```rust
+    /// use paranoid_android::EventValue;
+    ///
+    /// let mut buf = Vec::new();
+    /// EventValue::List(vec![EventValue::Int(1), EventValue::String("ok".to_owned())]).encode(&mut buf);
+    /// assert_eq!(buf, [3, 2, 0, 1, 0, 0, 0, 2, 2, 0, 0, 0, b'o', b'k']);
+    /// ```
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            EventValue::Int(v) => {
+                buf.push(TYPE_INT);
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            EventValue::Long(v) => {
+                buf.push(TYPE_LONG);
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            EventValue::Float(v) => {
+                buf.push(TYPE_FLOAT);
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            EventValue::String(v) => {
+                buf.push(TYPE_STRING);
+                buf.extend_from_slice(&(v.len() as u32).to_le_bytes());
+                buf.extend_from_slice(v.as_bytes());
+            }
+            EventValue::List(v) => {
+                let len = v.len().min(u8::MAX as usize);
+                buf.push(TYPE_LIST);
+                buf.push(len as u8);
+                for value in &v[..len] {
+                    value.encode(buf);
+                }
+            }
+        }
+    }
+
+    /// Decodes a single value from the start of the given buffer,
+    /// returning it along with the number of bytes read.
+    ///
+    /// ```rust
+    /// use paranoid_android::EventValue;
+    ///
+    /// let value = EventValue::List(vec![EventValue::Float(0.5), EventValue::String("ok".to_owned())]);
+    /// let mut buf = Vec::new();
+    /// value.encode(&mut buf);
+    ///
+    /// assert_eq!(EventValue::decode(&buf).unwrap(), (value, buf.len()));
+    /// ```
+    pub fn decode(buf: &[u8]) -> io::Result<(Self, usize)> {
+        fn take<const N: usize>(buf: &[u8], at: usize) -> io::Result<[u8; N]> {
+            buf.get(at..at + N)
+                .map(|b| b.try_into().unwrap())
+                .ok_or_else(|| invalid_data("truncated event log value"))
+        }
+
+        let ty = *buf
+            .first()
+            .ok_or_else(|| invalid_data("truncated event log value"))?;
+        match ty {
+            TYPE_INT => Ok((EventValue::Int(i32::from_le_bytes(take(buf, 1)?)), 5)),
+            TYPE_LONG => Ok((EventValue::Long(i64::from_le_bytes(take(buf, 1)?)), 9)),
+            TYPE_FLOAT => Ok((EventValue::Float(f32::from_le_bytes(take(buf, 1)?)), 5)),
+            TYPE_STRING => {
+                let len = u32::from_le_bytes(take(buf, 1)?) as usize;
+                let bytes = buf
+                    .get(5..)
+                    .and_then(|b| b.get(..len))
+                    .ok_or_else(|| invalid_data("truncated event log string"))?;
+                let value = String::from_utf8_lossy(bytes).into_owned();
+                Ok((EventValue::String(value), 5 + len))
+            }
+            TYPE_LIST => {
+                let [len] = take(buf, 1)?;
+                let mut values = Vec::with_capacity(len as usize);
+                let mut read = 2;
+                for _ in 0..len {
+                    let (value, len) = Self::decode(&buf[read..])?;
+                    values.push(value);
+                    read += len;
+                }
+                Ok((EventValue::List(values), read))
+            }
+            _ => Err(invalid_data("unknown event log value type")),
+        }
+    }
+
+    /// Converts this value to the given type, if it fits.
+    fn convert(self, ty: EventType) -> Option<Self> {
+        match (ty, self) {
+            (EventType::Int, EventValue::Int(v)) => Some(EventValue::Int(v)),
+            (EventType::Int, EventValue::Long(v)) => i32::try_from(v).ok().map(EventValue::Int),
+            (EventType::Long, EventValue::Int(v)) => Some(EventValue::Long(v.into())),
+            (EventType::Long, EventValue::Long(v)) => Some(EventValue::Long(v)),
+            (EventType::Float, EventValue::Float(v)) => Some(EventValue::Float(v)),
+            (EventType::Float, EventValue::Int(v)) => Some(EventValue::Float(v as f32)),
+            (EventType::Float, EventValue::Long(v)) => Some(EventValue::Float(v as f32)),
+            (EventType::String, EventValue::Int(v)) => Some(EventValue::String(v.to_string())),
+            (EventType::String, EventValue::Long(v)) => Some(EventValue::String(v.to_string())),
+            (EventType::String, EventValue::Float(v)) => Some(EventValue::String(v.to_string())),
+            (EventType::String, EventValue::String(v)) => Some(EventValue::String(v)),
+            (EventType::List, EventValue::List(v)) => Some(EventValue::List(v)),
+            (EventType::List, value) => Some(EventValue::List(vec![value])),
+            _ => None,
+        }
+    }
+}
+
+impl EventTags {
+    /// Returns a new, empty mapping.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a mapping from the given event name to the given tag number.
+    pub fn with(mut self, name: impl ToString, tag: u32) -> Self {
+        self.insert(name, tag);
+        self
+    }
+
+    /// Adds a mapping from the given event name to the given tag number and field types.
+    ///
+    /// Events are encoded with the given fields, by name, converting their values to the given types.
+    pub fn with_fields<F>(
+        mut self,
+        name: impl ToString,
+        tag: u32,
+        fields: impl IntoIterator<Item = (F, EventType)>,
+    ) -> Self
+    where
+        F: ToString,
+    {
+        self.insert_fields(name, tag, fields);
+        self
+    }
+
+    /// Adds a mapping from the given event name to the given tag number.
+    pub fn insert(&mut self, name: impl ToString, tag: u32) {
+        self.insert_fields(name, tag, Vec::<(String, EventType)>::new());
+    }
+
+    /// Adds a mapping from the given event name to the given tag number and field types.
+    ///
+    /// Events are encoded with the given fields, by name, converting their values to the given types.
+    pub fn insert_fields<F>(
+        &mut self,
+        name: impl ToString,
+        tag: u32,
+        fields: impl IntoIterator<Item = (F, EventType)>,
+    ) where
+        F: ToString,
+    {
+        let fields = fields
+            .into_iter()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect();
+        self.tags.insert(name.to_string(), EventTag { tag, fields });
+    }
+
+    /// Adds a mapping from the given event name to the given tag number and field types,
+    /// or returns an error if it can't be written to an `event-log-tags` file.
+    ///
+    /// This is the case if the name or the tag number is already mapped,
+    /// if the name is empty or contains whitespace,
+    /// or if a field name is empty or contains whitespace, `(`, `)`, `|` or `,`.
+    ///
+    /// ```rust
+    /// use paranoid_android::{EventTags, EventType};
+    ///
+    /// let mut tags = EventTags::new();
+    /// tags.try_insert_fields("battery_level", 2722, [("level", EventType::Long)]).unwrap();
+    /// assert!(tags.try_insert_fields("battery_state", 2722, [("level", EventType::Long)]).is_err());
+    /// assert!(tags.try_insert_fields("battery state", 2723, [("level", EventType::Long)]).is_err());
+    /// ```
+    pub fn try_insert_fields<F>(
+        &mut self,
+        name: impl ToString,
+        tag: u32,
+        fields: impl IntoIterator<Item = (F, EventType)>,
+    ) -> io::Result<()>
+    where
+        F: ToString,
+    {
+        let name = name.to_string();
+        if name.contains(char::is_whitespace) {
+            return Err(invalid_data("invalid event log tag name"));
+        }
+        self.check_new(&name, tag)?;
+
+        let fields: Vec<_> = fields
+            .into_iter()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect();
+        let invalid = |field: &str| {
+            field.is_empty() || field.contains(|c: char| c.is_whitespace() || "()|,".contains(c))
+        };
+        if fields.iter().any(|(field, _)| invalid(field)) {
+            return Err(invalid_data("invalid event log field name"));
+        }
+
+        self.tags.insert(name, EventTag { tag, fields });
+        Ok(())
+    }
+
+    /// Returns an error if the given name is empty or if the name or the tag number is already mapped.
+    fn check_new(&self, name: &str, tag: u32) -> io::Result<()> {
+        if name.is_empty() {
+            return Err(invalid_data("invalid event log tag name"));
+        }
+        if self.tags.contains_key(name) {
+            return Err(invalid_data("duplicate event log tag name"));
+        }
+        if self.tags.values().any(|t| t.tag == tag) {
+            return Err(invalid_data("duplicate event log tag number"));
+        }
+        Ok(())
+    }
+
+    /// Returns the tag number of the given event name.
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.tags.get(name).map(|t| t.tag)
+    }
+
+    /// Writes this mapping in the `event-log-tags` format, sorted by tag number.
+    pub fn write_event_log_tags(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut tags: Vec<_> = self.tags.iter().collect();
+        tags.sort_by_key(|(name, t)| (t.tag, name.as_str()));
+
+        for (name, t) in tags {
+            write!(writer, "{} {}", t.tag, name)?;
+            for (i, (field, ty)) in t.fields.iter().enumerate() {
+                let separator = if i == 0 { " " } else { "," };
+                write!(writer, "{}({}|{})", separator, field, *ty as u8)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes an event log record with the given tag and payload.
+    pub fn decode(&self, tag: u32, payload: &[u8]) -> io::Result<DecodedEvent> {
+        let (name, t) = self
+            .tags
+            .iter()
+            .find(|(_, t)| t.tag == tag)
+            .ok_or_else(|| invalid_data("unknown event log tag"))?;
+
+        let values = match EventValue::decode(payload)?.0 {
+            EventValue::List(values) => values,
+            value => vec![value],
+        };
+        let fields = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| match t.fields.get(i) {
+                Some((field, _)) => (field.clone(), value),
+                None => (i.to_string(), value),
+            })
+            .collect();
+
+        Ok(DecodedEvent {
+            name: name.clone(),
+            fields,
+        })
+    }
+}
+
+impl FromStr for EventTags {
+    type Err = io::Error;
+
+    /// Parses an `event-log-tags` file.
+    ///
+    /// Field names may contain any text but `|`, like those of the platform's `/system/etc/event-log-tags`,
+    /// even though [`try_insert_fields`](EventTags::try_insert_fields) doesn't accept all of them.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tags = EventTags::new();
+
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(3, char::is_whitespace);
+            let (tag, name) = match (parts.next(), parts.next()) {
+                (Some(tag), Some(name)) => (tag, name),
+                _ => return Err(invalid_data("missing event log tag name")),
+            };
+            let tag = tag
+                .parse()
+                .map_err(|_| invalid_data("invalid event log tag number"))?;
+
+            let mut fields = Vec::new();
+            for field in parts.next().unwrap_or_default().split(',') {
+                let field = field.trim();
+                if field.is_empty() {
+                    continue;
+                }
+
+                let mut parts = field
+                    .trim_start_matches('(')
+                    .trim_end_matches(')')
+                    .split('|');
+                let name = parts.next().unwrap_or_default().trim();
+                if name.is_empty() {
+                    return Err(invalid_data("invalid event log field name"));
+                }
+                let ty = match parts.next().map(str::trim) {
+                    Some("1") => EventType::Int,
+                    Some("2") => EventType::Long,
+                    Some("3") => EventType::String,
+                    Some("4") => EventType::List,
+                    Some("5") => EventType::Float,
+                    _ => return Err(invalid_data("invalid event log field type")),
+                };
+                fields.push((name, ty));
+            }
+
+            tags.check_new(name, tag)?;
+            tags.insert_fields(name, tag, fields);
+        }
+
+        Ok(tags)
+    }
+}
+
+/// Returns a tag number derived from the given event name,
+/// which stays the same across builds and releases.
+///
+/// ```rust
+/// assert_eq!(
+///     paranoid_android::stable_tag("battery_level"),
+///     paranoid_android::stable_tag("battery_level"),
+/// );
+/// ```
+pub fn stable_tag(name: &str) -> u32 {
+    // FNV-1a, mapped to a range unlikely to clash with platform tags and fitting in an `i32`
+    let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    });
+    10_000_000 + hash % 1_000_000_000
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl EventLogLayer {
+    /// Returns a new [`EventLogLayer`] using the given tags.
+    pub fn new(tags: EventTags) -> Self {
+        Self::with_backend(tags, Default::default())
+    }
+}
+
+impl<B: Backend> EventLogLayer<B> {
+    /// Returns a new [`EventLogLayer`] using the given tags and writing to the given [`Backend`].
+    pub fn with_backend(tags: EventTags, backend: B) -> Self {
+        Self {
+            tags,
+            backend,
+            errors: Default::default(),
+        }
+    }
+
+    /// Sets a callback invoked with every error encountered while encoding or writing a record.
+    ///
+    /// ```rust
+    /// use std::io;
+    ///
+    /// use paranoid_android::{EventLogLayer, EventTags, EventType, Memory};
+    /// use tracing_subscriber::prelude::*;
+    ///
+    /// let tags = EventTags::new().with_fields("battery_level", 2722, [("level", EventType::Int)]);
+    /// let layer = EventLogLayer::with_backend(tags, Memory::new())
+    ///     .with_error_handler(|e| assert_eq!(e.kind(), io::ErrorKind::InvalidInput));
+    /// let errors = layer.error_count();
+    ///
+    /// tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+    ///     tracing::info!(name: "battery_level", level = "full");
+    /// });
+    /// assert_eq!(errors.get(), 1);
+    /// ```
+    pub fn with_error_handler(
+        mut self,
+        handler: impl Fn(&io::Error) + Send + Sync + 'static,
+    ) -> Self {
+        self.errors.callback = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns a [`Counter`] of the errors encountered while encoding or writing records.
+    pub fn error_count(&self) -> Counter {
+        self.errors.count.clone()
+    }
+
+    /// Returns the payload of the given event, encoded following the fields of its tag.
+    fn encode(&self, t: &EventTag, event: &Event<'_>) -> io::Result<Vec<u8>> {
+        let mut visitor = EventVisitor(Vec::new());
+        event.record(&mut visitor);
+
+        let values = match t.fields.is_empty() {
+            true => visitor.0.into_iter().map(|(_, value)| value).collect(),
+            false => {
+                let mut values = Vec::with_capacity(t.fields.len());
+                for (field, ty) in &t.fields {
+                    let value = visitor
+                        .0
+                        .iter()
+                        .position(|(name, _)| name == field)
+                        .map(|i| visitor.0.swap_remove(i).1)
+                        .ok_or_else(|| invalid_input("missing event log field"))?;
+                    let value = value
+                        .convert(*ty)
+                        .ok_or_else(|| invalid_input("invalid event log field value"))?;
+                    values.push(value);
+                }
+                values
+            }
+        };
+
+        let mut payload = Vec::new();
+        EventValue::List(values).encode(&mut payload);
+        Ok(payload)
+    }
+}
+
+impl<S, B> tracing_subscriber::Layer<S> for EventLogLayer<B>
+where
+    S: Subscriber,
+    B: Backend + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let t = match self.tags.tags.get(event.metadata().name()) {
+            Some(t) => t,
+            None => return,
+        };
+
+        let result = self
+            .encode(t, event)
+            .and_then(|payload| self.backend.write_event(t.tag, &payload));
+        if let Err(e) = result {
+            self.errors.report(&e);
+        }
+    }
+}
+
+/// A visitor collecting the fields of an event, but its message, with the value types matching theirs.
+struct EventVisitor(Vec<(&'static str, EventValue)>);
+
+impl EventVisitor {
+    fn push(&mut self, field: &Field, value: EventValue) {
+        if field.name() != MESSAGE_FIELD {
+            self.0.push((field.name(), value));
+        }
+    }
+}
+
+impl Visit for EventVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, EventValue::Float(value as f32));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, EventValue::Long(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match i64::try_from(value) {
+            Ok(value) => self.record_i64(field, value),
+            Err(_) => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, EventValue::Int(value as i32));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, EventValue::String(value.to_owned()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, EventValue::String(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: EventValue) {
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+        assert_eq!(EventValue::decode(&buf).unwrap(), (value, buf.len()));
+    }
+
+    #[test]
+    fn round_trips_every_value_type() {
+        round_trip(EventValue::Int(i32::MIN));
+        round_trip(EventValue::Int(-1));
+        round_trip(EventValue::Long(i64::MAX));
+        round_trip(EventValue::Float(-0.25));
+        round_trip(EventValue::String(String::new()));
+        round_trip(EventValue::String("héllo, wörld".to_owned()));
+        round_trip(EventValue::List(Vec::new()));
+    }
+
+    #[test]
+    fn round_trips_nested_lists() {
+        round_trip(EventValue::List(vec![
+            EventValue::Int(1),
+            EventValue::List(vec![
+                EventValue::String("nested".to_owned()),
+                EventValue::List(vec![EventValue::Long(2), EventValue::Float(3.5)]),
+            ]),
+            EventValue::List(Vec::new()),
+        ]));
+    }
+
+    #[test]
+    fn truncates_long_lists() {
+        let values: Vec<_> = (0..300).map(EventValue::Int).collect();
+        let mut buf = Vec::new();
+        EventValue::List(values.clone()).encode(&mut buf);
+
+        let (value, len) = EventValue::decode(&buf).unwrap();
+        assert_eq!(value, EventValue::List(values[..255].to_vec()));
+        assert_eq!(len, buf.len());
+    }
+
+    #[test]
+    fn rejects_truncated_payloads() {
+        let mut buf = Vec::new();
+        EventValue::List(vec![
+            EventValue::Long(1),
+            EventValue::String("text".to_owned()),
+        ])
+        .encode(&mut buf);
+
+        for len in 0..buf.len() {
+            let e = EventValue::decode(&buf[..len]).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "length {}", len);
+        }
+        assert!(EventValue::decode(&[9]).is_err());
+    }
+
+    #[test]
+    fn parses_event_log_tags() {
+        let tags: EventTags = "# comment\n\n2722 battery_level (level|2),(charging|1)\n42 answer\n"
+            .parse()
+            .unwrap();
+        assert_eq!(tags.get("battery_level"), Some(2722));
+        assert_eq!(tags.get("answer"), Some(42));
+
+        let mut file = Vec::new();
+        tags.write_event_log_tags(&mut file).unwrap();
+        assert_eq!(
+            file,
+            b"42 answer\n2722 battery_level (level|2),(charging|1)\n"
+        );
+    }
+
+    #[test]
+    fn parses_platform_field_names() {
+        let file = "42 answer (to life the universe etc|3)\n\
+                    2719 configuration_changed (config mask|1|5)\n";
+        let tags: EventTags = file.parse().unwrap();
+        assert_eq!(tags.get("configuration_changed"), Some(2719));
+
+        let mut payload = Vec::new();
+        EventValue::String("yes".to_owned()).encode(&mut payload);
+        let event = tags.decode(42, &payload).unwrap();
+        assert_eq!(event.fields[0].0, "to life the universe etc");
+    }
+
+    #[test]
+    fn rejects_invalid_event_log_tags() {
+        for file in [
+            "2722 battery_level\n2722 battery_state\n",
+            "2722 battery_level\n2723 battery_level\n",
+            "2722  battery_level\n",
+            "2722 battery_level (|2)\n",
+            "2722 battery_level (level|6)\n",
+            "battery_level 2722\n",
+            "2722\n",
+        ] {
+            assert!(file.parse::<EventTags>().is_err(), "{:?}", file);
+        }
+    }
+
+    #[test]
+    fn encodes_fields_by_name_into_their_declared_types() {
+        use tracing_subscriber::prelude::*;
+
+        let memory = crate::Memory::new();
+        let tags = EventTags::new().with_fields(
+            "counted",
+            42,
+            [("count", EventType::Int), ("label", EventType::String)],
+        );
+        let layer = EventLogLayer::with_backend(tags, memory.clone());
+        let errors = layer.error_count();
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(name: "counted", label = 7, count = 3, "ignored");
+            tracing::info!(name: "counted", count = 3);
+            tracing::info!(name: "counted", count = u64::MAX, label = "big");
+        });
+
+        let mut payload = Vec::new();
+        EventValue::List(vec![EventValue::Int(3), EventValue::String("7".to_owned())])
+            .encode(&mut payload);
+
+        let events = memory.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].payload, payload);
+        assert_eq!(errors.get(), 2);
+    }
+}
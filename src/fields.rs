@@ -0,0 +1,202 @@
+use std::{cell::RefCell, error::Error, fmt};
+
+use tracing_core::field::{Field, Visit};
+use tracing_subscriber::{
+    field::{MakeVisitor, VisitFmt, VisitOutput},
+    fmt::format::{DefaultFields, Writer},
+};
+
+use crate::logging::{Buffer, Priority};
+
+/// A field formatter reading reserved fields, which override how a single event is logged,
+/// and stripping them from the formatted message.
+///
+/// The reserved fields are:
+///
+/// * `android.tag`: the tag of the event
+/// * `android.buffer`: the [buffer](Buffer) of the event, as a name like `crash`
+/// * `android.priority`: the [priority](Priority) of the event, as a name like `fatal` or a letter like `F`
+///
+/// It wraps another field formatter, [`DefaultFields`] by default, and is used by the [layers](crate::Layer) of this crate.
+///
+/// ```rust
+/// use paranoid_android::{Buffer, Memory, Priority};
+/// use tracing_subscriber::prelude::*;
+///
+/// let memory = Memory::new();
+/// let subscriber = tracing_subscriber::registry()
+///     .with(paranoid_android::with_backend("app", Buffer::Main, memory.clone()));
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::error!(android.tag = "crash", android.buffer = "crash", android.priority = "fatal", "bye");
+///     tracing::info!("hello");
+/// });
+///
+/// let records = memory.take();
+/// assert_eq!(
+///     (records[0].tag.as_str(), records[0].buffer, records[0].priority),
+///     ("crash", Buffer::Crash, Priority::Fatal),
+/// );
+/// assert!(records[0].message.ends_with(": bye\n"));
+/// assert_eq!(
+///     (records[1].tag.as_str(), records[1].buffer, records[1].priority),
+///     ("app", Buffer::Main, Priority::Info),
+/// );
+/// ```
+#[derive(Debug, Default)]
+pub struct ReservedFields<N = DefaultFields> {
+    inner: N,
+}
+
+/// The visitor produced by [`ReservedFields`].
+#[derive(Debug)]
+pub struct ReservedVisitor<V> {
+    inner: V,
+    overrides: Overrides,
+}
+
+/// The overrides read from the reserved fields of an event.
+#[derive(Debug, Default)]
+pub(crate) struct Overrides {
+    pub(crate) tag: Option<String>,
+    pub(crate) buffer: Option<Buffer>,
+    pub(crate) priority: Option<Priority>,
+}
+
+const TAG: &str = "android.tag";
+const BUFFER: &str = "android.buffer";
+const PRIORITY: &str = "android.priority";
+
+thread_local! {
+    /// The overrides of the last event formatted on this thread, taken by the writer.
+    ///
+    /// The `fmt` layer formats an event right before making its writer,
+    /// so the writer always takes the overrides of the event it writes.
+    static OVERRIDES: RefCell<Option<Overrides>> = const { RefCell::new(None) };
+}
+
+impl<N> ReservedFields<N> {
+    /// Returns a new [`ReservedFields`] wrapping the given field formatter.
+    pub fn new(inner: N) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, N> MakeVisitor<Writer<'a>> for ReservedFields<N>
+where
+    N: MakeVisitor<Writer<'a>>,
+{
+    type Visitor = ReservedVisitor<N::Visitor>;
+
+    fn make_visitor(&self, target: Writer<'a>) -> Self::Visitor {
+        ReservedVisitor {
+            inner: self.inner.make_visitor(target),
+            overrides: Overrides::default(),
+        }
+    }
+}
+
+impl<V: Visit> ReservedVisitor<V> {
+    /// Records the given value if the field is reserved, returning whether it was.
+    fn reserved(&mut self, field: &Field, value: &dyn fmt::Debug) -> bool {
+        match field.name() {
+            TAG => self.overrides.tag = Some(debug_to_string(value)),
+            BUFFER => self.overrides.buffer = debug_to_string(value).parse().ok(),
+            PRIORITY => self.overrides.priority = debug_to_string(value).parse().ok(),
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl<V: Visit> Visit for ReservedVisitor<V> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if !self.reserved(field, &value) {
+            self.inner.record_f64(field, value)
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if !self.reserved(field, &value) {
+            self.inner.record_i64(field, value)
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if !self.reserved(field, &value) {
+            self.inner.record_u64(field, value)
+        }
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        if !self.reserved(field, &value) {
+            self.inner.record_i128(field, value)
+        }
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        if !self.reserved(field, &value) {
+            self.inner.record_u128(field, value)
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if !self.reserved(field, &value) {
+            self.inner.record_bool(field, value)
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if !self.reserved(field, &format_args!("{}", value)) {
+            self.inner.record_str(field, value)
+        }
+    }
+
+    fn record_bytes(&mut self, field: &Field, value: &[u8]) {
+        if !self.reserved(field, &value) {
+            self.inner.record_bytes(field, value)
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        if !self.reserved(field, &format_args!("{}", value)) {
+            self.inner.record_error(field, value)
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.reserved(field, value) {
+            self.inner.record_debug(field, value)
+        }
+    }
+}
+
+impl<V: VisitOutput<fmt::Result>> VisitOutput<fmt::Result> for ReservedVisitor<V> {
+    fn finish(self) -> fmt::Result {
+        // spans are formatted with the same visitor, but their overrides are
+        // replaced by those of the next event before any writer takes them
+        let overrides = self.overrides;
+        OVERRIDES.with(|o| *o.borrow_mut() = Some(overrides));
+        self.inner.finish()
+    }
+}
+
+impl<V: VisitFmt> VisitFmt for ReservedVisitor<V> {
+    fn writer(&mut self) -> &mut dyn fmt::Write {
+        self.inner.writer()
+    }
+}
+
+impl Overrides {
+    /// Takes the overrides of the last event formatted on this thread.
+    pub(crate) fn take() -> Option<Self> {
+        OVERRIDES
+            .try_with(|o| o.try_borrow_mut().ok()?.take())
+            .ok()
+            .flatten()
+    }
+}
+
+fn debug_to_string(value: &dyn fmt::Debug) -> String {
+    format!("{:?}", value)
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use tracing_core::{callsite::Identifier, subscriber::Interest, Metadata};
+use tracing_subscriber::layer::{Context, Filter};
+
+use crate::{
+    logging::Priority,
+    property::{min_priority, system_properties, PropertySource},
+    sync::{read, write},
+    tag::{TagStrategy, Tags},
+    watcher::PropertyWatcher,
+    Error,
+};
+
+/// A [per-layer filter](tracing_subscriber::layer#per-layer-filtering) honouring the `log.tag` system properties,
+/// so that `setprop log.tag.MyTag D` controls verbosity before events are formatted.
+///
+/// On Android with the `api-30` feature, `__android_log_is_loggable` is consulted directly.
+/// Otherwise, the properties are read from a [`PropertySource`] and interpreted the same way,
+/// which defaults to the system properties on Android and to no properties at all elsewhere.
+///
+/// Whether a callsite is enabled is computed once and cached.
+///
+/// ```rust
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory, PropertyFilter, TagStrategy};
+/// use tracing_subscriber::prelude::*;
+///
+/// let properties = |name: &str| match name {
+///     "log.tag.app" => Some("D".to_owned()),
+///     "log.tag.noisy" => Some("S".to_owned()),
+///     _ => None,
+/// };
+///
+/// let memory = Memory::new();
+/// let make_writer = AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone())
+///     .with_tag_strategy(TagStrategy::CrateName);
+/// let filter = PropertyFilter::new("app")
+///     .with_tag_strategy(TagStrategy::CrateName)
+///     .with_properties(properties);
+/// let subscriber = tracing_subscriber::registry()
+///     .with(tracing_subscriber::fmt::layer().with_writer(make_writer).with_filter(filter));
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::trace!(target: "app", "filtered");
+///     tracing::debug!(target: "app", "logged");
+///     tracing::error!(target: "noisy", "suppressed");
+///     tracing::debug!(target: "other", "below the default priority");
+///     tracing::info!(target: "other", "logged");
+/// });
+///
+/// let tags: Vec<_> = memory.take().into_iter().map(|r| r.tag).collect();
+/// assert_eq!(tags, ["app", "other"]);
+/// ```
+pub struct PropertyFilter {
+    tags: Tags,
+    source: Source,
+    default: Priority,
+    state: Arc<State>,
+}
+
+/// The state of a [`PropertyFilter`] shared with its [watcher](PropertyWatcher).
+#[derive(Debug, Default)]
+pub(crate) struct State {
+    /// Whether each callsite is enabled.
+    cache: RwLock<HashMap<Identifier, bool>>,
+    /// The tags whose properties have been read.
+    tags: RwLock<HashSet<String>>,
+}
+
+enum Source {
+    #[cfg(all(target_os = "android", feature = "api-30"))]
+    LibLog,
+    Properties(Arc<dyn PropertySource>),
+}
+
+impl PropertyFilter {
+    /// Returns a new [`PropertyFilter`] for records with the given tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains an interior NUL byte. See [`try_new`](Self::try_new) for a fallible version.
+    pub fn new(tag: impl ToString) -> Self {
+        Self::try_new(tag).unwrap()
+    }
+
+    /// Returns a new [`PropertyFilter`] for records with the given tag,
+    /// or an error if the tag contains an interior NUL byte.
+    pub fn try_new(tag: impl ToString) -> Result<Self, Error> {
+        #[cfg(all(target_os = "android", feature = "api-30"))]
+        let source = Source::LibLog;
+        #[cfg(not(all(target_os = "android", feature = "api-30")))]
+        let source = Source::Properties(system_properties());
+
+        Ok(Self {
+            tags: Tags::new(CString::new(tag.to_string())?),
+            source,
+            default: Priority::Info,
+            state: Default::default(),
+        })
+    }
+
+    /// Sets how the tag of each record is derived from its target. Defaults to [`TagStrategy::Fixed`].
+    ///
+    /// This should match the strategy of the [writer](crate::AndroidLogMakeWriter::with_tag_strategy).
+    pub fn with_tag_strategy(self, strategy: TagStrategy) -> Self {
+        Self {
+            tags: self.tags.with_strategy(strategy),
+            state: Default::default(),
+            ..self
+        }
+    }
+
+    /// Reads properties from the given source instead of the system properties.
+    pub fn with_properties(self, source: impl PropertySource + 'static) -> Self {
+        Self {
+            source: Source::Properties(Arc::new(source)),
+            state: Default::default(),
+            ..self
+        }
+    }
+
+    /// Sets the minimum priority of records whose tag has no property set. Defaults to [`Priority::Info`], like Android.
+    pub fn with_default_priority(self, default: Priority) -> Self {
+        Self {
+            default,
+            state: Default::default(),
+            ..self
+        }
+    }
+
+    /// Returns a [`PropertyWatcher`] reloading this filter when the properties it depends on change.
+    ///
+    /// The filter should be fully configured before calling this method.
+    pub fn watcher(&self) -> PropertyWatcher {
+        let source = match &self.source {
+            #[cfg(all(target_os = "android", feature = "api-30"))]
+            Source::LibLog => Arc::new(crate::property::SystemProperties),
+            Source::Properties(source) => source.clone(),
+        };
+        PropertyWatcher::new(source, self.state.clone())
+    }
+
+    /// Returns whether records originating from the callsite with the given metadata are loggable.
+    fn is_loggable(&self, meta: &Metadata<'_>) -> bool {
+        let callsite = meta.callsite();
+        if let Some(&loggable) = read(&self.state.cache).get(&callsite) {
+            return loggable;
+        }
+
+        let tag = self.tags.get(meta);
+        write(&self.state.tags).insert(tag.to_string_lossy().into_owned());
+        let priority = Priority::from(*meta.level());
+        let loggable = match &self.source {
+            #[cfg(all(target_os = "android", feature = "api-30"))]
+            Source::LibLog => {
+                use ndk_sys::__android_log_is_loggable;
+
+                let priority = priority.as_raw().0 as i32;
+                let default = self.default.as_raw().0 as i32;
+                unsafe { __android_log_is_loggable(priority, tag.as_ptr(), default) != 0 }
+            }
+            Source::Properties(source) => {
+                min_priority(&**source, &tag.to_string_lossy(), self.default)
+                    .is_some_and(|min| priority >= min)
+            }
+        };
+        write(&self.state.cache).insert(callsite, loggable);
+        loggable
+    }
+}
+
+impl<S> Filter<S> for PropertyFilter {
+    fn enabled(&self, meta: &Metadata<'_>, _: &Context<'_, S>) -> bool {
+        self.is_loggable(meta)
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        match self.is_loggable(meta) {
+            true => Interest::always(),
+            false => Interest::never(),
+        }
+    }
+}
+
+impl fmt::Debug for PropertyFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PropertyFilter")
+            .field("tags", &self.tags)
+            .field("source", &self.source)
+            .field("default", &self.default)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(all(target_os = "android", feature = "api-30"))]
+            Source::LibLog => f.write_str("LibLog"),
+            Source::Properties(_) => f.debug_tuple("Properties").field(&..).finish(),
+        }
+    }
+}
+
+impl State {
+    /// Returns the tags whose properties have been read.
+    pub(crate) fn tags(&self) -> Vec<String> {
+        read(&self.tags).iter().cloned().collect()
+    }
+
+    /// Forgets whether each callsite is enabled.
+    pub(crate) fn clear(&self) {
+        write(&self.cache).clear();
+    }
+}
@@ -7,11 +7,15 @@ use tracing_subscriber::{
     registry::LookupSpan,
 };
 
-use crate::{AndroidLogMakeWriter, Buffer};
+use crate::{
+    backend::{Backend, DefaultBackend},
+    fields::ReservedFields,
+    AndroidLogMakeWriter, Buffer,
+};
 
 /// A [`Layer`](tracing_subscriber::Layer) that writes formatted representations of `tracing` events as Android logs.
-pub type Layer<S, N = format::DefaultFields, E = format::Full> =
-    fmt::Layer<S, N, format::Format<E, ()>, AndroidLogMakeWriter>;
+pub type Layer<S, N = ReservedFields, E = format::Full, B = DefaultBackend> =
+    fmt::Layer<S, N, format::Format<E, ()>, AndroidLogMakeWriter<B>>;
 
 /// Returns a new [formatting layer](Layer) with the given tag,
 /// which can be [composed](tracing_subscriber::Layer) with other layers to construct a [`Subscriber`].
@@ -29,8 +33,40 @@ pub fn with_buffer<S>(tag: impl ToString, buffer: Buffer) -> Layer<S>
 where
     S: Subscriber,
     for<'a> S: LookupSpan<'a>,
+{
+    with_backend(tag, buffer, Default::default())
+}
+
+/// Returns a new [formatting layer](Layer) with the given tag, using the given [Android log buffer](Buffer)
+/// and writing to the given [`Backend`],
+/// which can be [composed](tracing_subscriber::Layer) with other layers to construct a [`Subscriber`].
+pub fn with_backend<S, B>(
+    tag: impl ToString,
+    buffer: Buffer,
+    backend: B,
+) -> Layer<S, ReservedFields, format::Full, B>
+where
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+    B: Backend + 'static,
+{
+    from_writer(AndroidLogMakeWriter::with_backend(
+        tag.to_string(),
+        buffer,
+        backend,
+    ))
+}
+
+pub(crate) fn from_writer<S, B>(
+    make_writer: AndroidLogMakeWriter<B>,
+) -> Layer<S, ReservedFields, format::Full, B>
+where
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+    B: Backend + 'static,
 {
     fmt::Layer::new()
+        .fmt_fields(ReservedFields::default())
         .event_format(Format::default().with_level(false).without_time())
-        .with_writer(AndroidLogMakeWriter::with_buffer(tag.to_string(), buffer))
+        .with_writer(make_writer)
 }
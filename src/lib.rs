@@ -1,5 +1,3 @@
-#![cfg(target_os = "android")]
-
 //! Integration layer between `tracing` and Android logs.
 //!
 //! This crate provides a [`MakeWriter`](tracing_subscriber::fmt::MakeWriter) suitable for writing Android logs.
@@ -7,6 +5,10 @@
 //! It is designed as an integration with the [`fmt`](tracing_subscriber::fmt) subscriber from `tracing-subscriber`
 //! and as such inherits all of its features and customization options.
 //!
+//! Records are written through a [`Backend`], which is `liblog` on Android.
+//! Off-device, records are [discarded](Discard) by default.
+//! The [`Memory`] backend is available on every target and can be used to exercise logging off-device.
+//!
 //! ## Usage
 //!
 //! ```rust
@@ -19,7 +21,7 @@
 //! # let other_layer = paranoid_android::layer("other");
 //! #
 //! use tracing_subscriber::filter::LevelFilter;
-//! use tracing_subscriber::fmt::FmtSpan;
+//! use tracing_subscriber::fmt::format::FmtSpan;
 //! use tracing_subscriber::prelude::*;
 //!
 //! let android_layer = paranoid_android::layer(env!("CARGO_PKG_NAME"))
@@ -27,34 +29,113 @@
 //!     .with_thread_names(true)
 //!     .with_filter(LevelFilter::DEBUG);
 //!
-//! tracing_subcriber::registry()
+//! tracing_subscriber::registry()
 //!     .with(android_layer)
 //!     .with(other_layer)
 //!     .init();
 //! ```
 //!
+//! Every option of the layer can also be set in one place using an [`AndroidLayerBuilder`].
+//!
 //! ## Cargo features
 //!
 //! * `api-30`: Enables support for Android API level 30 and source location information
+//! * `env-filter`: Enables reading `RUST_LOG`-style directives from a system property
 
 #![warn(rust_2018_idioms, missing_debug_implementations, missing_docs)]
 
+mod backend;
+mod builder;
+#[cfg(unix)]
+mod callsite;
+#[cfg(all(target_os = "android", feature = "api-30"))]
+mod capture;
+mod chunk;
+mod crash;
+mod error;
+mod events;
+mod fields;
+mod filter;
 mod layer;
 mod logging;
+mod property;
+mod recorder;
+#[cfg(unix)]
+mod redirect;
+#[cfg(unix)]
+mod signal;
+mod span_buffer;
+mod sync;
+mod tag;
+mod watcher;
 mod writer;
 
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Registry};
 
+#[cfg(target_os = "android")]
+pub use self::backend::LibLog;
+#[cfg(unix)]
+pub use self::backend::Logd;
+#[cfg(all(target_os = "android", feature = "api-30"))]
+pub use self::capture::capture_liblog;
+#[cfg(feature = "env-filter")]
+pub use self::property::property_env_filter;
+#[cfg(target_os = "android")]
+pub use self::property::SystemProperties;
+#[cfg(unix)]
+pub use self::redirect::StdioRedirect;
+#[cfg(unix)]
+pub use self::signal::SignalHandler;
 pub use self::{
-    layer::{layer, with_buffer, Layer},
-    logging::Buffer,
-    writer::{AndroidLogMakeWriter, AndroidLogWriter},
+    backend::{
+        minimum_level, sync_minimum_priority, Backend, DefaultBackend, Discard, Memory,
+        MemoryEvent, MemoryRecord, Record,
+    },
+    builder::{AndroidLayerBuilder, FilteredLayer, SyncedFilter},
+    chunk::{chunks, reassemble, Chunks},
+    crash::{install_panic_hook, PanicHook},
+    error::{Error, ParseError},
+    events::{stable_tag, DecodedEvent, EventLogLayer, EventTags, EventType, EventValue},
+    fields::{ReservedFields, ReservedVisitor},
+    filter::PropertyFilter,
+    layer::{layer, with_backend, with_buffer, Layer},
+    logging::{Buffer, Priority},
+    property::PropertySource,
+    recorder::{dump_recent, FlightRecorder, FlightRecorderWriter},
+    span_buffer::SpanBufferLayer,
+    tag::TagStrategy,
+    watcher::PropertyWatcher,
+    writer::{AndroidLogMakeWriter, AndroidLogWriter, Counter, FatalPolicy, NulPolicy, Overflow},
 };
 
 /// Creates a [`Subscriber`](tracing_core::Subscriber) with the given tag
 /// and attempts to set it as the [global default subscriber] in the current scope, panicking if this fails.
 ///
+/// See [`try_init`] for a version that never panics.
+///
 /// [global default subscriber]: https://docs.rs/tracing/0.1/tracing/dispatcher/index.html#setting-the-default-subscriber
 pub fn init(tag: impl ToString) {
     Registry::default().with(layer(tag)).init();
 }
+
+/// Creates a [`Subscriber`](tracing_core::Subscriber) with the given tag
+/// and attempts to set it as the [global default subscriber] in the current scope.
+///
+/// Returns an error if the tag contains an interior NUL byte or if a global default subscriber has already been set.
+///
+/// ```rust
+/// use paranoid_android::Error;
+///
+/// assert!(matches!(paranoid_android::try_init("invalid\0tag"), Err(Error::InvalidTag(_))));
+/// assert!(paranoid_android::try_init("tag").is_ok());
+/// assert!(matches!(paranoid_android::try_init("tag"), Err(Error::AlreadyInitialized(_))));
+/// ```
+///
+/// [global default subscriber]: https://docs.rs/tracing/0.1/tracing/dispatcher/index.html#setting-the-default-subscriber
+pub fn try_init(tag: impl ToString) -> Result<(), Error> {
+    let make_writer = AndroidLogMakeWriter::try_new(tag.to_string())?;
+    Registry::default()
+        .with(layer::from_writer(make_writer))
+        .try_init()?;
+    Ok(())
+}
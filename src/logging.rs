@@ -1,49 +1,129 @@
+#[cfg(target_os = "android")]
 use ndk_sys::{android_LogPriority, log_id};
-use tracing_core::Level;
+use std::{fmt, str::FromStr};
 
+use tracing_core::{Level, LevelFilter};
+
+use crate::error::ParseError;
+
+/// An [Android log priority](https://developer.android.com/ndk/reference/group/logging#android_logpriority).
 #[repr(u32)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Priority {
-    Verbose = android_LogPriority::ANDROID_LOG_VERBOSE.0,
-    Debug = android_LogPriority::ANDROID_LOG_DEBUG.0,
-    Info = android_LogPriority::ANDROID_LOG_INFO.0,
-    Warn = android_LogPriority::ANDROID_LOG_WARN.0,
-    Error = android_LogPriority::ANDROID_LOG_ERROR.0,
-    Fatal = android_LogPriority::ANDROID_LOG_FATAL.0,
+    /// Verbose logging.
+    Verbose = 2,
+    /// Debug logging.
+    Debug = 3,
+    /// Informational logging.
+    Info = 4,
+    /// Warning logging, for recoverable failures.
+    Warn = 5,
+    /// Error logging, for unrecoverable failures.
+    Error = 6,
+    /// Fatal logging, for use when aborting.
+    Fatal = 7,
 }
 
 /// An [Android log buffer](https://developer.android.com/ndk/reference/group/logging#log_id).
 #[repr(u32)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum Buffer {
     /// Let the logging function choose the best log target.
-    Default = log_id::LOG_ID_DEFAULT.0,
+    #[default]
+    Default = 0x7FFF_FFFF,
 
     /// The main log buffer.
     ///
     /// This is the only log buffer available to apps.
-    Main = log_id::LOG_ID_MAIN.0,
+    Main = 0,
 
     /// The crash log buffer.
-    Crash = log_id::LOG_ID_CRASH.0,
+    Crash = 4,
     /// The statistics log buffer.
-    Stats = log_id::LOG_ID_STATS.0,
+    Stats = 5,
     /// The event log buffer.
-    Events = log_id::LOG_ID_EVENTS.0,
+    Events = 2,
     /// The security log buffer.
-    Security = log_id::LOG_ID_SECURITY.0,
+    Security = 6,
     /// The system log buffer.
-    System = log_id::LOG_ID_SYSTEM.0,
+    System = 3,
     /// The kernel log buffer.
-    Kernel = log_id::LOG_ID_KERNEL.0,
+    Kernel = 7,
     /// The radio log buffer.
-    Radio = log_id::LOG_ID_RADIO.0,
+    Radio = 1,
 }
 
 impl Priority {
-    pub fn as_raw(self) -> android_LogPriority {
+    #[cfg(target_os = "android")]
+    pub(crate) fn as_raw(self) -> android_LogPriority {
         android_LogPriority(self as u32)
     }
+
+    /// Returns the priority with the given raw value, if any.
+    #[cfg(unix)]
+    pub(crate) fn from_raw(priority: i32) -> Option<Self> {
+        let priority = match priority {
+            2 => Priority::Verbose,
+            3 => Priority::Debug,
+            4 => Priority::Info,
+            5 => Priority::Warn,
+            6 => Priority::Error,
+            7 => Priority::Fatal,
+            _ => return None,
+        };
+        Some(priority)
+    }
+
+    /// Returns the minimum priority of the records enabled by the given filter.
+    ///
+    /// Since there is no priority disabling every record, [`LevelFilter::OFF`] maps to [`Priority::Fatal`].
+    pub(crate) fn from_level_filter(filter: LevelFilter) -> Self {
+        match filter.into_level() {
+            Some(level) => level.into(),
+            None => Priority::Fatal,
+        }
+    }
+}
+
+/// Parses a priority from its case-insensitive name, its letter as printed by `logcat` or its raw value.
+///
+/// ```rust
+/// use paranoid_android::Priority;
+///
+/// assert_eq!("warn".parse(), Ok(Priority::Warn));
+/// assert_eq!("F".parse(), Ok(Priority::Fatal));
+/// assert_eq!("3".parse(), Ok(Priority::Debug));
+/// assert!("loud".parse::<Priority>().is_err());
+/// assert_eq!(Priority::Verbose.to_string().parse(), Ok(Priority::Verbose));
+/// ```
+impl FromStr for Priority {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let priority = match s.trim().to_ascii_lowercase().as_str() {
+            "verbose" | "v" | "trace" | "2" => Priority::Verbose,
+            "debug" | "d" | "3" => Priority::Debug,
+            "info" | "i" | "4" => Priority::Info,
+            "warn" | "warning" | "w" | "5" => Priority::Warn,
+            "error" | "e" | "6" => Priority::Error,
+            "fatal" | "assert" | "f" | "a" | "7" => Priority::Fatal,
+            _ => return Err(ParseError::new("priority")),
+        };
+        Ok(priority)
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Priority::Verbose => "verbose",
+            Priority::Debug => "debug",
+            Priority::Info => "info",
+            Priority::Warn => "warn",
+            Priority::Error => "error",
+            Priority::Fatal => "fatal",
+        })
+    }
 }
 
 impl From<Level> for Priority {
@@ -71,13 +151,54 @@ impl From<Priority> for Level {
 }
 
 impl Buffer {
+    #[cfg(target_os = "android")]
     pub(crate) fn as_raw(self) -> log_id {
         log_id(self as u32)
     }
 }
 
-impl Default for Buffer {
-    fn default() -> Self {
-        Self::Default
+/// Parses a buffer from its case-insensitive name, as used by `logcat -b`, or its raw value.
+///
+/// ```rust
+/// use paranoid_android::Buffer;
+///
+/// assert_eq!("crash".parse(), Ok(Buffer::Crash));
+/// assert_eq!("1".parse(), Ok(Buffer::Radio));
+/// assert!("all".parse::<Buffer>().is_err());
+/// assert_eq!(Buffer::Events.to_string().parse(), Ok(Buffer::Events));
+/// ```
+impl FromStr for Buffer {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let buffer = match s.trim().to_ascii_lowercase().as_str() {
+            "default" => Buffer::Default,
+            "main" | "0" => Buffer::Main,
+            "radio" | "1" => Buffer::Radio,
+            "events" | "2" => Buffer::Events,
+            "system" | "3" => Buffer::System,
+            "crash" | "4" => Buffer::Crash,
+            "stats" | "5" => Buffer::Stats,
+            "security" | "6" => Buffer::Security,
+            "kernel" | "7" => Buffer::Kernel,
+            _ => return Err(ParseError::new("buffer")),
+        };
+        Ok(buffer)
+    }
+}
+
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Buffer::Default => "default",
+            Buffer::Main => "main",
+            Buffer::Crash => "crash",
+            Buffer::Stats => "stats",
+            Buffer::Events => "events",
+            Buffer::Security => "security",
+            Buffer::System => "system",
+            Buffer::Kernel => "kernel",
+            Buffer::Radio => "radio",
+        })
     }
 }
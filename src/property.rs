@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+#[cfg(feature = "env-filter")]
+use tracing_subscriber::EnvFilter;
+
+use crate::logging::Priority;
+
+/// A source of Android system properties, like `log.tag.MyTag`.
+///
+/// On Android, properties are read using `SystemProperties`.
+/// Any function taking a property name and returning its value implements this trait,
+/// which allows substituting properties off-device.
+///
+/// ```rust
+/// use paranoid_android::PropertySource;
+///
+/// let source = |name: &str| (name == "log.tag.app").then(|| "D".to_owned());
+/// assert_eq!(source.get("log.tag.app").as_deref(), Some("D"));
+/// assert_eq!(source.get("log.tag.other"), None);
+/// ```
+pub trait PropertySource: Send + Sync {
+    /// Returns the value of the property with the given name, if it is set.
+    fn get(&self, name: &str) -> Option<String>;
+}
+
+/// The Android system properties, read using `__system_property_get`.
+#[cfg(target_os = "android")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemProperties;
+
+impl<F> PropertySource for F
+where
+    F: Fn(&str) -> Option<String> + Send + Sync,
+{
+    fn get(&self, name: &str) -> Option<String> {
+        self(name)
+    }
+}
+
+#[cfg(target_os = "android")]
+impl PropertySource for SystemProperties {
+    fn get(&self, name: &str) -> Option<String> {
+        use std::{ffi::CString, os::raw::c_char};
+
+        let name = CString::new(name).ok()?;
+        let mut value = [0 as c_char; libc::PROP_VALUE_MAX as usize];
+        let len = unsafe { libc::__system_property_get(name.as_ptr(), value.as_mut_ptr()) };
+        if len <= 0 {
+            return None;
+        }
+
+        let value: Vec<u8> = value[..len as usize].iter().map(|&c| c as u8).collect();
+        Some(String::from_utf8_lossy(&value).into_owned())
+    }
+}
+
+/// Returns an [`EnvFilter`] parsed from the `RUST_LOG`-style directives held by the property with the given name,
+/// or from the given default directives if it isn't set.
+///
+/// Since apps can't set environment variables on Android, a property like `debug.<package>.rust_log`
+/// can be set using `adb shell setprop` instead. Invalid directives are ignored.
+///
+/// ```rust
+/// use paranoid_android::property_env_filter;
+///
+/// let properties = |name: &str| (name == "debug.app.rust_log").then(|| "app=trace,warn".to_owned());
+///
+/// let filter = property_env_filter(&properties, "debug.app.rust_log", "info");
+/// assert_eq!(filter.to_string(), "app=trace,warn");
+///
+/// let filter = property_env_filter(&properties, "debug.other.rust_log", "info");
+/// assert_eq!(filter.to_string(), "info");
+/// ```
+#[cfg(feature = "env-filter")]
+pub fn property_env_filter(source: &dyn PropertySource, name: &str, default: &str) -> EnvFilter {
+    let directives = source
+        .get(name)
+        .filter(|directives| !directives.trim().is_empty());
+    EnvFilter::builder().parse_lossy(directives.as_deref().unwrap_or(default))
+}
+
+/// Returns the system properties on Android, and a source without any property elsewhere.
+pub(crate) fn system_properties() -> Arc<dyn PropertySource> {
+    #[cfg(target_os = "android")]
+    let source = Arc::new(SystemProperties);
+    #[cfg(not(target_os = "android"))]
+    let source = Arc::new(|_: &str| None);
+    source
+}
+
+/// Returns the minimum priority of the records with the given tag according to the given properties,
+/// or `None` if they are all suppressed.
+///
+/// This mirrors `__android_log_is_loggable`: `log.tag.<tag>` is checked first, then `persist.log.tag.<tag>`,
+/// then the global `log.tag` and `persist.log.tag`. The first set property whose value starts with
+/// one of `V`, `D`, `I`, `W`, `E`, `F`, `A` or `S` (for suppress) wins.
+pub(crate) fn min_priority(
+    source: &dyn PropertySource,
+    tag: &str,
+    default: Priority,
+) -> Option<Priority> {
+    for name in &names(tag) {
+        let value = match source.get(name) {
+            Some(value) => value,
+            None => continue,
+        };
+        let priority = match value.trim_start().chars().next() {
+            Some('S' | 's') => return None,
+            Some(c) if "VDIWEFAvdiwefa".contains(c) => c.to_string().parse().ok(),
+            _ => None,
+        };
+        if priority.is_some() {
+            return priority;
+        }
+    }
+    Some(default)
+}
+
+/// Returns the names of the properties controlling the records with the given tag, by order of precedence.
+pub(crate) fn names(tag: &str) -> [String; 4] {
+    [
+        format!("log.tag.{}", tag),
+        format!("persist.log.tag.{}", tag),
+        "log.tag".to_owned(),
+        "persist.log.tag".to_owned(),
+    ]
+}
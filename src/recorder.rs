@@ -0,0 +1,363 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    io::{self, Write},
+    sync::{Arc, Mutex, Weak},
+    thread::{self, ThreadId},
+};
+
+use tracing_core::{Level, LevelFilter, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::{
+    backend::{Backend, DefaultBackend},
+    sync::lock,
+    writer::BufferedRecord,
+    AndroidLogMakeWriter, AndroidLogWriter,
+};
+
+/// A [`MakeWriter`] keeping the last events below a level in memory instead of writing them,
+/// until an `ERROR` event occurs.
+///
+/// Events at or above the [level](Self::with_level) are written right away by the wrapped [`AndroidLogMakeWriter`].
+/// The others are formatted and kept in a bounded ring buffer, either shared by all threads or [per thread](Self::per_thread),
+/// which is written out before every `ERROR` event, when [`dump_recent`] is called
+/// or when a [`PanicHook`](crate::PanicHook) reports a panic.
+/// This gives the context of a failure without the cost of writing debug logs all the time.
+///
+/// Since every event is formatted, the layer using this writer shouldn't filter out the levels to keep.
+///
+/// ```rust
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, FlightRecorder, Memory, Priority};
+/// use tracing_subscriber::prelude::*;
+///
+/// let memory = Memory::new();
+/// let make_writer = AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone());
+/// let recorder = FlightRecorder::new(make_writer, 2);
+/// let subscriber = tracing_subscriber::registry()
+///     .with(paranoid_android::layer::<_>("app").with_target(false).with_writer(recorder));
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::debug!("opening");
+///     tracing::debug!("reading");
+///     tracing::info!("started");
+///     tracing::trace!("parsing");
+///     tracing::error!("failed");
+///     tracing::debug!("closing");
+///     paranoid_android::dump_recent();
+/// });
+///
+/// let records: Vec<_> = memory.take().into_iter().map(|r| (r.priority, r.message)).collect();
+/// assert_eq!(
+///     records,
+///     [
+///         (Priority::Info, "started\n".to_owned()),
+///         (Priority::Debug, "reading\n".to_owned()),
+///         (Priority::Verbose, "parsing\n".to_owned()),
+///         (Priority::Error, "failed\n".to_owned()),
+///         (Priority::Debug, "closing\n".to_owned()),
+///     ]
+/// );
+/// ```
+#[derive(Debug)]
+pub struct FlightRecorder<B: Backend = DefaultBackend> {
+    shared: Arc<Shared<B>>,
+    level: LevelFilter,
+}
+
+/// The writer produced by [`FlightRecorder`].
+#[derive(Debug)]
+pub struct FlightRecorderWriter<'a, B: Backend = DefaultBackend>(Kind<'a, B>);
+
+#[derive(Debug)]
+enum Kind<'a, B: Backend> {
+    Direct(AndroidLogWriter<'a, B>),
+    Recorded(&'a Shared<B>, Option<BufferedRecord>),
+}
+
+/// The state of a [`FlightRecorder`] shared with [`dump_recent`].
+#[derive(Debug)]
+struct Shared<B: Backend> {
+    make_writer: AndroidLogMakeWriter<B>,
+    rings: Mutex<Rings>,
+    /// A weak reference to this state, registered with the threads it keeps events for.
+    this: Weak<dyn Dump>,
+}
+
+#[derive(Debug)]
+struct Rings {
+    capacity: usize,
+    per_thread: bool,
+    next_seq: u64,
+    /// The events kept for each thread, or for all of them under `None`.
+    entries: HashMap<Option<ThreadId>, VecDeque<BufferedRecord>>,
+}
+
+trait Dump: Send + Sync {
+    fn dump_recent(&self);
+
+    /// Discards the events kept for the given thread.
+    fn forget(&self, thread: ThreadId);
+}
+
+/// The recorders keeping events for the current thread, which forget them when it exits.
+struct ThreadRings {
+    thread: ThreadId,
+    recorders: RefCell<Vec<Weak<dyn Dump>>>,
+}
+
+static RECORDERS: Mutex<Vec<Weak<dyn Dump>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static THREAD_RINGS: ThreadRings = ThreadRings {
+        thread: thread::current().id(),
+        recorders: RefCell::new(Vec::new()),
+    };
+}
+
+/// Writes out the events kept by every [`FlightRecorder`], from the oldest to the newest, and forgets them.
+pub fn dump_recent() {
+    let recorders: Vec<_> = lock(&RECORDERS).iter().filter_map(Weak::upgrade).collect();
+    for recorder in recorders {
+        recorder.dump_recent();
+    }
+}
+
+impl<B> FlightRecorder<B>
+where
+    B: Backend + Send + Sync + 'static,
+{
+    /// Returns a new [`FlightRecorder`] keeping at most `capacity` events
+    /// and writing through the given [`AndroidLogMakeWriter`].
+    pub fn new(make_writer: AndroidLogMakeWriter<B>, capacity: usize) -> Self {
+        let shared = Arc::new_cyclic(|this: &Weak<Shared<B>>| Shared {
+            make_writer,
+            rings: Mutex::new(Rings {
+                capacity,
+                per_thread: false,
+                next_seq: 0,
+                entries: HashMap::new(),
+            }),
+            this: this.clone(),
+        });
+
+        let mut recorders = lock(&RECORDERS);
+        recorders.retain(|recorder| recorder.strong_count() > 0);
+        recorders.push(shared.this.clone());
+        drop(recorders);
+
+        Self {
+            shared,
+            level: LevelFilter::INFO,
+        }
+    }
+}
+
+impl<B: Backend> FlightRecorder<B> {
+    /// Sets the level from which events are written right away instead of being kept. Defaults to `INFO`.
+    ///
+    /// `ERROR` events are always written right away, so [`LevelFilter::OFF`] keeps all the others.
+    pub fn with_level(self, level: impl Into<LevelFilter>) -> Self {
+        Self {
+            level: level.into(),
+            ..self
+        }
+    }
+
+    /// Keeps up to the capacity for each thread rather than for all threads together.
+    ///
+    /// An `ERROR` event then only writes out the events of its own thread.
+    /// The events of a thread are discarded when it exits.
+    pub fn per_thread(self) -> Self {
+        lock(&self.shared.rings).per_thread = true;
+        self
+    }
+}
+
+impl<'a, B: Backend + 'a> MakeWriter<'a> for FlightRecorder<B> {
+    type Writer = FlightRecorderWriter<'a, B>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        FlightRecorderWriter(Kind::Direct(self.shared.make_writer.make_writer()))
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        if *meta.level() == Level::ERROR {
+            let per_thread = lock(&self.shared.rings).per_thread;
+            self.shared.dump(per_thread.then(|| thread::current().id()));
+        }
+        if *meta.level() == Level::ERROR || *meta.level() <= self.level {
+            let writer = self.shared.make_writer.make_writer_for(meta);
+            return FlightRecorderWriter(Kind::Direct(writer));
+        }
+
+        let entry = self.shared.make_writer.buffered(meta);
+        FlightRecorderWriter(Kind::Recorded(&self.shared, entry))
+    }
+}
+
+impl<B: Backend> Write for FlightRecorderWriter<'_, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Kind::Direct(writer) => writer.write(buf),
+            Kind::Recorded(_, entry) => {
+                if let Some(entry) = entry {
+                    entry.message.extend_from_slice(buf);
+                }
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Kind::Direct(writer) => writer.flush(),
+            Kind::Recorded(..) => Ok(()),
+        }
+    }
+}
+
+impl<B: Backend> Drop for FlightRecorderWriter<'_, B> {
+    fn drop(&mut self) {
+        if let Kind::Recorded(shared, entry) = &mut self.0 {
+            if let Some(entry) = entry.take().filter(|entry| !entry.message.is_empty()) {
+                if lock(&shared.rings).push(entry) {
+                    shared.forget_on_exit();
+                }
+            }
+        }
+    }
+}
+
+impl<B: Backend> Shared<B> {
+    /// Writes out the events kept for the given thread, or for all threads.
+    fn dump(&self, thread: Option<ThreadId>) {
+        // the lock isn't held while writing, in case the backend logs through `tracing` itself
+        let entries = lock(&self.rings).take(thread);
+        for entry in entries {
+            self.make_writer.replay(entry);
+        }
+    }
+
+    /// Makes the current thread discard the events kept for it by this recorder when it exits.
+    fn forget_on_exit(&self) {
+        let _ = THREAD_RINGS.try_with(|rings| {
+            let mut recorders = rings.recorders.borrow_mut();
+            recorders.retain(|recorder| recorder.strong_count() > 0);
+            if !recorders.iter().any(|recorder| recorder.ptr_eq(&self.this)) {
+                recorders.push(self.this.clone());
+            }
+        });
+    }
+}
+
+impl<B> Dump for Shared<B>
+where
+    B: Backend + Send + Sync,
+{
+    fn dump_recent(&self) {
+        self.dump(None)
+    }
+
+    fn forget(&self, thread: ThreadId) {
+        lock(&self.rings).entries.remove(&Some(thread));
+    }
+}
+
+impl Drop for ThreadRings {
+    fn drop(&mut self) {
+        for recorder in self.recorders.get_mut().drain(..) {
+            if let Some(recorder) = recorder.upgrade() {
+                recorder.forget(self.thread);
+            }
+        }
+    }
+}
+
+impl Rings {
+    /// Keeps the given event, returning whether a ring was created for the current thread.
+    fn push(&mut self, mut entry: BufferedRecord) -> bool {
+        if self.capacity == 0 {
+            return false;
+        }
+        entry.seq = self.next_seq;
+        self.next_seq += 1;
+
+        let key = self.per_thread.then(|| thread::current().id());
+        let created = key.is_some() && !self.entries.contains_key(&key);
+        let entries = self.entries.entry(key).or_default();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+        created
+    }
+
+    /// Removes and returns the events of the given thread, or of all threads, from the oldest to the newest.
+    fn take(&mut self, thread: Option<ThreadId>) -> Vec<BufferedRecord> {
+        let mut entries: Vec<_> = match thread {
+            Some(thread) => self
+                .entries
+                .remove(&Some(thread))
+                .into_iter()
+                .flatten()
+                .collect(),
+            None => self
+                .entries
+                .drain()
+                .flat_map(|(_, entries)| entries)
+                .collect(),
+        };
+        entries.sort_by_key(|entry| entry.seq);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_core::{dispatcher, Dispatch};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{Buffer, Memory, Priority};
+
+    #[test]
+    fn events_from_info_are_written_right_away_by_default() {
+        let memory = Memory::new();
+        let make_writer =
+            AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone());
+        let recorder = FlightRecorder::new(make_writer, 4);
+        let subscriber =
+            tracing_subscriber::registry().with(crate::layer::<_>("app").with_writer(recorder));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("kept");
+            tracing::info!("started");
+            tracing::warn!("slow");
+        });
+
+        let priorities: Vec<_> = memory.take().into_iter().map(|r| r.priority).collect();
+        assert_eq!(priorities, [Priority::Info, Priority::Warn]);
+    }
+
+    #[test]
+    fn per_thread_rings_are_removed_when_their_thread_exits() {
+        let make_writer =
+            AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, Memory::new());
+        let recorder = FlightRecorder::new(make_writer, 4).per_thread();
+        let shared = recorder.shared.clone();
+        let dispatch = Dispatch::new(
+            tracing_subscriber::registry().with(crate::layer::<_>("app").with_writer(recorder)),
+        );
+
+        let spawned = dispatch.clone();
+        thread::spawn(move || dispatcher::with_default(&spawned, || tracing::debug!("working")))
+            .join()
+            .unwrap();
+        dispatcher::with_default(&dispatch, || tracing::debug!("waiting"));
+
+        let rings = lock(&shared.rings);
+        let threads: Vec<_> = rings.entries.keys().collect();
+        assert_eq!(threads, [&Some(thread::current().id())]);
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    thread,
+};
+
+use tracing_core::Level;
+
+use crate::callsite::DynamicCallsite;
+
+/// Redirects the standard output and error of the process into `tracing` events.
+///
+/// On Android, anything written to stdout and stderr, like the output of `println!` or `printf`, is discarded.
+/// Once [installed](Self::install), each line written to a redirected stream becomes an event
+/// with the target and level configured for that stream, emitted from a background thread
+/// to the [global default subscriber].
+///
+/// The subscriber must not write to the redirected streams itself, since its output would be redirected back to it.
+///
+/// ```rust
+/// use std::{thread, time::Duration};
+///
+/// use paranoid_android::{Buffer, Memory, Priority, StdioRedirect};
+/// use tracing::Level;
+/// use tracing_subscriber::prelude::*;
+///
+/// let memory = Memory::new();
+/// tracing_subscriber::registry()
+///     .with(paranoid_android::with_backend("app", Buffer::Main, memory.clone()))
+///     .init();
+///
+/// StdioRedirect::new()
+///     .with_stdout("my_app::stdout", Level::WARN)
+///     .without_stderr()
+///     .install()
+///     .unwrap();
+/// println!("hello from stdout");
+///
+/// let mut records = Vec::new();
+/// for _ in 0..100 {
+///     records = memory.take();
+///     if !records.is_empty() {
+///         break;
+///     }
+///     thread::sleep(Duration::from_millis(10));
+/// }
+/// assert_eq!(records[0].priority, Priority::Warn);
+/// assert_eq!(records[0].message, "my_app::stdout: hello from stdout\n");
+/// ```
+///
+/// [global default subscriber]: https://docs.rs/tracing/0.1/tracing/dispatcher/index.html#setting-the-default-subscriber
+#[derive(Debug, Clone)]
+pub struct StdioRedirect {
+    stdout: Option<Stream>,
+    stderr: Option<Stream>,
+}
+
+#[derive(Debug, Clone)]
+struct Stream {
+    target: String,
+    level: Level,
+}
+
+/// A file descriptor replaced by a pipe, along with a duplicate of the original one to restore it.
+struct Redirected {
+    fd: RawFd,
+    saved: File,
+}
+
+impl StdioRedirect {
+    /// Returns a new [`StdioRedirect`] redirecting stdout at the `INFO` level with the `stdout` target
+    /// and stderr at the `WARN` level with the `stderr` target.
+    pub fn new() -> Self {
+        Self {
+            stdout: Some(Stream::new("stdout", Level::INFO)),
+            stderr: Some(Stream::new("stderr", Level::WARN)),
+        }
+    }
+
+    /// Redirects stdout using the given target and level.
+    pub fn with_stdout(self, target: impl ToString, level: Level) -> Self {
+        Self {
+            stdout: Some(Stream::new(target, level)),
+            ..self
+        }
+    }
+
+    /// Redirects stderr using the given target and level.
+    pub fn with_stderr(self, target: impl ToString, level: Level) -> Self {
+        Self {
+            stderr: Some(Stream::new(target, level)),
+            ..self
+        }
+    }
+
+    /// Leaves stdout untouched.
+    pub fn without_stdout(self) -> Self {
+        Self {
+            stdout: None,
+            ..self
+        }
+    }
+
+    /// Leaves stderr untouched.
+    pub fn without_stderr(self) -> Self {
+        Self {
+            stderr: None,
+            ..self
+        }
+    }
+
+    /// Redirects the streams for the rest of the life of the process,
+    /// spawning a background thread per stream.
+    ///
+    /// If a stream can't be redirected, the streams are left untouched.
+    pub fn install(self) -> io::Result<()> {
+        let stdout = match self.stdout {
+            Some(stdout) => Some(stdout.redirect(libc::STDOUT_FILENO)?),
+            None => None,
+        };
+        if let Some(stderr) = self.stderr {
+            if let Err(e) = stderr.redirect(libc::STDERR_FILENO) {
+                // closing the pipe stops the thread reading stdout
+                if let Some(stdout) = stdout {
+                    let _ = stdout.restore();
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for StdioRedirect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream {
+    fn new(target: impl ToString, level: Level) -> Self {
+        Self {
+            target: target.to_string(),
+            level,
+        }
+    }
+
+    /// Replaces the given file descriptor with a pipe read from a background thread.
+    fn redirect(self, fd: RawFd) -> io::Result<Redirected> {
+        let [read, write] = pipe()?;
+        // the pipe is closed when `reader` is dropped on failure
+        let reader = unsafe { File::from_raw_fd(read) };
+
+        let saved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+        if saved < 0 {
+            let e = io::Error::last_os_error();
+            unsafe { libc::close(write) };
+            return Err(e);
+        }
+        let redirected = Redirected {
+            fd,
+            saved: unsafe { File::from_raw_fd(saved) },
+        };
+
+        let result = check(unsafe { libc::dup2(write, fd) });
+        unsafe { libc::close(write) };
+        result?;
+
+        let spawned = thread::Builder::new()
+            .name(format!("{}-redirect", self.target))
+            .spawn(move || self.forward(BufReader::new(reader)));
+        match spawned {
+            Ok(_) => Ok(redirected),
+            Err(e) => {
+                let _ = redirected.restore();
+                Err(e)
+            }
+        }
+    }
+
+    /// Dispatches each line read from the given reader as an event, until the pipe is closed.
+    fn forward(self, mut reader: impl BufRead) {
+        let callsite = DynamicCallsite::get(&self.target, self.level, None, None);
+
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(&['\n', '\r'][..]);
+            if !text.is_empty() {
+                callsite.dispatch(text);
+            }
+        }
+    }
+}
+
+impl Redirected {
+    /// Puts the original file descriptor back in place, closing the pipe.
+    fn restore(self) -> io::Result<()> {
+        check(unsafe { libc::dup2(self.saved.as_raw_fd(), self.fd) })
+    }
+}
+
+/// Returns the read and write ends of a new pipe, neither of which is inherited by child processes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn pipe() -> io::Result<[RawFd; 2]> {
+    let mut fds = [0; 2];
+    check(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) })?;
+    Ok(fds)
+}
+
+/// Returns the read and write ends of a new pipe, neither of which is inherited by child processes.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn pipe() -> io::Result<[RawFd; 2]> {
+    let mut fds = [0; 2];
+    check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    for fd in fds {
+        if let Err(e) = check(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) }) {
+            unsafe {
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+            }
+            return Err(e);
+        }
+    }
+    Ok(fds)
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,494 @@
+use std::{
+    cell::UnsafeCell,
+    ffi::CString,
+    fmt::{self, Write as _},
+    io, mem,
+    os::{raw::c_int, unix::io::IntoRawFd, unix::net::UnixDatagram},
+    path::{Path, PathBuf},
+    ptr,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU8, AtomicUsize, Ordering},
+};
+
+use crate::{
+    backend::{current_tid, header, Logd},
+    logging::{Buffer, Priority},
+    Error,
+};
+
+/// A handler for fatal signals, like `SIGSEGV` or `SIGABRT`, logging them before the process dies.
+///
+/// The usual writers can't be used from a signal handler since they allocate and take locks.
+/// Instead, this handler writes directly to logd's socket, connected ahead of time,
+/// using only buffers allocated at installation.
+/// It records the signal, the faulting address and the ID of the crashing thread,
+/// followed by the last few messages written by any [`AndroidLogWriter`](crate::AndroidLogWriter).
+/// The signal is then passed on to the previous handler, which is the default one unless another was installed,
+/// like debuggerd's on Android: a previous `SA_SIGINFO` handler is called with the original signal information,
+/// while other handlers are restored, either to fault again when the handler returns or to get the signal raised again.
+///
+/// ```rust
+/// use std::os::unix::net::UnixDatagram;
+///
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory, SignalHandler};
+/// use tracing_subscriber::fmt::MakeWriter;
+///
+/// let path = std::env::temp_dir().join(format!("logdw-signal-{}", std::process::id()));
+/// let logd = UnixDatagram::bind(&path).unwrap();
+///
+/// match unsafe { libc::fork() } {
+///     0 => {
+///         SignalHandler::new("app").with_path(&path).install().unwrap();
+///         let make_writer = AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, Memory::new());
+///         std::io::Write::write_all(&mut make_writer.make_writer(), b"last words").unwrap();
+///         drop(make_writer);
+///         unsafe { libc::raise(libc::SIGABRT) };
+///         unreachable!();
+///     }
+///     child => {
+///         let mut status = 0;
+///         unsafe { libc::waitpid(child, &mut status, 0) };
+///         assert!(libc::WIFSIGNALED(status));
+///         assert_eq!(libc::WTERMSIG(status), libc::SIGABRT);
+///     }
+/// }
+///
+/// let mut datagram = [0; 256];
+/// let message = |datagram: &[u8]| {
+///     assert_eq!(datagram[0], 4); // crash buffer
+///     String::from_utf8_lossy(&datagram[12..]).into_owned()
+/// };
+///
+/// let len = logd.recv(&mut datagram).unwrap();
+/// assert!(message(&datagram[..len]).starts_with("app\0fatal signal 6 (SIGABRT)"));
+/// let len = logd.recv(&mut datagram).unwrap();
+/// assert_eq!(message(&datagram[..len]), "app\0last words\0");
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SignalHandler {
+    tag: String,
+    path: PathBuf,
+    buffer: Buffer,
+}
+
+/// The state of an installed handler, leaked so that it can be accessed from the handler.
+struct Config {
+    fd: c_int,
+    tag: CString,
+    buffer: Buffer,
+}
+
+/// A lock-free ring of the last messages written, readable from a signal handler.
+struct Ring {
+    next: AtomicUsize,
+    slots: [Slot; RECENT_COUNT],
+}
+
+/// A single message of the ring, protected by a sequence lock.
+///
+/// The sequence is odd while the message is written and `0` until it is written for the first time.
+struct Slot {
+    seq: AtomicUsize,
+    priority: AtomicU8,
+    len: AtomicUsize,
+    data: [AtomicU8; RECENT_LEN],
+}
+
+/// The previous actions of the handled signals, restored before raising them again.
+struct Previous(UnsafeCell<[mem::MaybeUninit<libc::sigaction>; SIGNALS.len()]>);
+
+/// A formatter writing to a fixed buffer, truncating what doesn't fit.
+struct StackWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+/// The number of messages kept for the handler.
+const RECENT_COUNT: usize = 8;
+/// The maximum length of the messages kept for the handler, in bytes.
+const RECENT_LEN: usize = 512;
+
+const SIGNALS: [c_int; 5] = [
+    libc::SIGABRT,
+    libc::SIGBUS,
+    libc::SIGFPE,
+    libc::SIGILL,
+    libc::SIGSEGV,
+];
+
+static CONFIG: AtomicPtr<Config> = AtomicPtr::new(ptr::null_mut());
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static RING: Ring = Ring {
+    next: AtomicUsize::new(0),
+    slots: [Slot::EMPTY; RECENT_COUNT],
+};
+static PREVIOUS: Previous = Previous(UnsafeCell::new([mem::MaybeUninit::uninit(); SIGNALS.len()]));
+
+// `PREVIOUS` is only written while installing the handler, before any signal can use it
+unsafe impl Sync for Previous {}
+
+impl SignalHandler {
+    /// Returns a new [`SignalHandler`] writing records with the given tag
+    /// to the [crash buffer](Buffer::Crash) of the [default socket](Logd::DEFAULT_PATH).
+    pub fn new(tag: impl ToString) -> Self {
+        Self {
+            tag: tag.to_string(),
+            path: Logd::DEFAULT_PATH.into(),
+            buffer: Buffer::Crash,
+        }
+    }
+
+    /// Writes records to the datagram socket at the given path.
+    pub fn with_path(self, path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().into(),
+            ..self
+        }
+    }
+
+    /// Writes records to the given [Android log buffer](Buffer). Defaults to [`Buffer::Crash`].
+    pub fn with_buffer(self, buffer: Buffer) -> Self {
+        Self { buffer, ..self }
+    }
+
+    /// Connects to the socket and installs the handler for `SIGABRT`, `SIGBUS`, `SIGFPE`, `SIGILL` and `SIGSEGV`.
+    ///
+    /// Returns an error if the tag contains an interior NUL byte, if the socket can't be connected to,
+    /// if a handler can't be installed or if one is already installed.
+    /// The previous handlers are left in place on failure.
+    pub fn install(self) -> Result<(), Error> {
+        if INSTALLED
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            let e = io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a signal handler is already installed",
+            );
+            return Err(Error::Setup(e));
+        }
+
+        let result = self.try_install();
+        if result.is_err() {
+            INSTALLED.store(false, Ordering::Release);
+        }
+        result
+    }
+
+    fn try_install(self) -> Result<(), Error> {
+        let tag = CString::new(self.tag)?;
+        let socket = connect(&self.path).map_err(Error::Setup)?;
+
+        // until the configuration is set, the handler only raises the signals again with their previous actions
+        let previous = unsafe { &mut *PREVIOUS.0.get() };
+        for (i, &signal) in SIGNALS.iter().enumerate() {
+            unsafe {
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = handle as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+                libc::sigemptyset(&mut action.sa_mask);
+                if libc::sigaction(signal, &action, previous[i].as_mut_ptr()) != 0 {
+                    let e = io::Error::last_os_error();
+                    for (&signal, previous) in SIGNALS.iter().zip(&previous[..i]) {
+                        libc::sigaction(signal, previous.as_ptr(), ptr::null_mut());
+                    }
+                    return Err(Error::Setup(e));
+                }
+            }
+        }
+
+        let config = Box::new(Config {
+            fd: socket.into_raw_fd(),
+            tag,
+            buffer: self.buffer,
+        });
+        // the configuration is leaked since a handler may use it at any time
+        CONFIG.store(Box::into_raw(config), Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Returns a non-blocking socket connected to the given path.
+fn connect(path: &Path) -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Keeps the given message for the signal handler, if one is installed.
+pub(crate) fn remember(priority: Priority, message: &[u8]) {
+    if INSTALLED.load(Ordering::Relaxed) {
+        RING.push(priority, message);
+    }
+}
+
+extern "C" fn handle(signal: c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let config = CONFIG.load(Ordering::Acquire);
+    if !config.is_null() {
+        let config = unsafe { &*config };
+
+        let mut buf = [0; RECENT_LEN];
+        let mut message = StackWriter::new(&mut buf);
+        let address = match info.is_null() {
+            true => ptr::null_mut(),
+            false => unsafe { (*info).si_addr() },
+        };
+        let _ = write!(
+            message,
+            "fatal signal {} ({}), fault addr {:p}, tid {}",
+            signal,
+            name(signal),
+            address,
+            current_tid(),
+        );
+        let len = message.len;
+        send(config, Priority::Fatal, &buf[..len]);
+
+        RING.for_each(&mut buf, |priority, message| {
+            send(config, priority, message)
+        });
+    }
+
+    let index = SIGNALS.iter().position(|&s| s == signal);
+    unsafe {
+        let previous = match index {
+            Some(index) => &*(*PREVIOUS.0.get())[index].as_ptr(),
+            None => {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+                return;
+            }
+        };
+
+        // the previous handler, like debuggerd's on Android, gets the original information about the fault
+        let handler = previous.sa_sigaction;
+        if previous.sa_flags & libc::SA_SIGINFO != 0
+            && handler != libc::SIG_DFL
+            && handler != libc::SIG_IGN
+        {
+            let handler: extern "C" fn(c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                mem::transmute(handler);
+            handler(signal, info, context);
+            return;
+        }
+
+        libc::sigaction(signal, previous, ptr::null_mut());
+        // faults raised by the CPU happen again once the handler returns, now handled by the previous action
+        if info.is_null() || (*info).si_code <= 0 {
+            libc::raise(signal);
+        }
+    }
+}
+
+/// Writes a record to the socket of the given configuration, without allocating.
+fn send(config: &Config, priority: Priority, message: &[u8]) {
+    let mut now: libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut now) };
+    let header = header(
+        config.buffer,
+        current_tid(),
+        now.tv_sec as u32,
+        now.tv_nsec as u32,
+    );
+    let priority = [priority as u8];
+    let tag = config.tag.as_bytes_with_nul();
+
+    let parts: [&[u8]; 5] = [&header, &priority, tag, message, &[0]];
+    let mut iov: [libc::iovec; 5] = unsafe { mem::zeroed() };
+    for (iov, part) in iov.iter_mut().zip(&parts) {
+        iov.iov_base = part.as_ptr() as *mut libc::c_void;
+        iov.iov_len = part.len();
+    }
+    unsafe { libc::writev(config.fd, iov.as_ptr(), iov.len() as c_int) };
+}
+
+fn name(signal: c_int) -> &'static str {
+    match signal {
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGILL => "SIGILL",
+        libc::SIGSEGV => "SIGSEGV",
+        _ => "unknown",
+    }
+}
+
+impl Ring {
+    fn push(&self, priority: Priority, message: &[u8]) {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % RECENT_COUNT;
+        self.slots[index].write(priority, message);
+    }
+
+    /// Calls the given function with each message of the ring, from the oldest to the newest,
+    /// using the given buffer to copy them.
+    fn for_each(&self, buf: &mut [u8; RECENT_LEN], mut f: impl FnMut(Priority, &[u8])) {
+        let next = self.next.load(Ordering::Relaxed);
+        for i in 0..RECENT_COUNT {
+            let slot = &self.slots[(next + i) % RECENT_COUNT];
+            if let Some((priority, len)) = slot.read(buf) {
+                f(priority, &buf[..len]);
+            }
+        }
+    }
+}
+
+impl Slot {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Slot = {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU8 = AtomicU8::new(0);
+        Slot {
+            seq: AtomicUsize::new(0),
+            priority: AtomicU8::new(0),
+            len: AtomicUsize::new(0),
+            data: [ZERO; RECENT_LEN],
+        }
+    };
+
+    fn write(&self, priority: Priority, message: &[u8]) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        // the slot is being written by another thread, which only happens when the ring wraps around concurrently
+        if seq % 2 == 1
+            || self
+                .seq
+                .compare_exchange(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+        fence(Ordering::Release);
+
+        let message = crate::chunk::truncate(message, RECENT_LEN);
+        let message = message.strip_suffix(b"\n").unwrap_or(message);
+        for (dst, &src) in self.data.iter().zip(message) {
+            dst.store(src, Ordering::Relaxed);
+        }
+        self.priority.store(priority as u8, Ordering::Relaxed);
+        self.len.store(message.len(), Ordering::Relaxed);
+
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+
+    /// Copies the message of the slot to the given buffer, returning its priority and length,
+    /// or `None` if the slot is empty or being written.
+    fn read(&self, buf: &mut [u8; RECENT_LEN]) -> Option<(Priority, usize)> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq == 0 || seq % 2 == 1 {
+            return None;
+        }
+
+        let len = self.len.load(Ordering::Relaxed).min(RECENT_LEN);
+        for (dst, src) in buf.iter_mut().zip(&self.data[..len]) {
+            *dst = src.load(Ordering::Relaxed);
+        }
+        let priority = self.priority.load(Ordering::Relaxed);
+
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != seq {
+            return None;
+        }
+        let priority = Priority::from_raw(priority.into()).unwrap_or(Priority::Fatal);
+        Some((priority, len))
+    }
+}
+
+impl<'a> StackWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+}
+
+impl fmt::Write for StackWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_is_rolled_back_on_failure_and_refused_once_done() {
+        let path = std::env::temp_dir().join(format!("logdw-install-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let result = SignalHandler::new("app").with_path(&path).install();
+        assert!(matches!(result, Err(Error::Setup(_))));
+        assert!(!INSTALLED.load(Ordering::Acquire));
+        assert!(CONFIG.load(Ordering::Acquire).is_null());
+
+        let _logd = UnixDatagram::bind(&path).unwrap();
+        SignalHandler::new("app")
+            .with_path(&path)
+            .install()
+            .unwrap();
+        match SignalHandler::new("app").with_path(&path).install() {
+            Err(Error::Setup(e)) => assert_eq!(e.kind(), io::ErrorKind::AlreadyExists),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn slots_keep_the_priority_of_their_message() {
+        let slot = Slot::EMPTY;
+        let mut buf = [0; RECENT_LEN];
+        assert_eq!(slot.read(&mut buf), None);
+
+        slot.write(Priority::Warn, b"careful\n");
+        assert_eq!(slot.read(&mut buf), Some((Priority::Warn, 7)));
+        assert_eq!(&buf[..7], b"careful");
+    }
+
+    #[test]
+    fn previous_handlers_see_the_original_fault() {
+        const CHILD: &str = "PARANOID_ANDROID_SIGNAL_CHILD";
+        const FAULT_ADDRESS: usize = 0x10;
+        const CHAINED: i32 = 42;
+
+        extern "C" fn previous(_: c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+            let info = unsafe { &*info };
+            let address = unsafe { info.si_addr() } as usize;
+            let code = match address == FAULT_ADDRESS && info.si_code > 0 {
+                true => CHAINED,
+                false => 1,
+            };
+            unsafe { libc::_exit(code) };
+        }
+
+        if std::env::var_os(CHILD).is_some() {
+            let path = std::env::temp_dir().join(format!("logdw-chain-{}", std::process::id()));
+            let _logd = UnixDatagram::bind(&path).unwrap();
+            unsafe {
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = previous as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_SIGINFO;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(libc::SIGSEGV, &action, ptr::null_mut());
+            }
+            SignalHandler::new("app")
+                .with_path(&path)
+                .install()
+                .unwrap();
+            let _ = std::fs::remove_file(&path);
+            unsafe { ptr::read_volatile(FAULT_ADDRESS as *const u8) };
+            unreachable!();
+        }
+
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "signal::tests::previous_handlers_see_the_original_fault",
+            ])
+            .env(CHILD, "1")
+            .output()
+            .unwrap()
+            .status;
+        assert_eq!(status.code(), Some(CHAINED));
+    }
+}
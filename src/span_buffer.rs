@@ -0,0 +1,410 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt,
+    io::{self, Write},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tracing_core::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+use tracing_subscriber::{
+    field::MakeVisitor,
+    fmt::{
+        format::{DefaultFields, Format, Full, Writer},
+        MakeWriter,
+    },
+    layer::Context,
+    registry::{LookupSpan, SpanRef},
+};
+
+use crate::{
+    backend::{Backend, DefaultBackend},
+    fields::ReservedFields,
+    writer::BufferedRecord,
+    AndroidLogMakeWriter, Counter,
+};
+
+/// A [`Layer`](tracing_subscriber::Layer) keeping the verbose events of each span in memory,
+/// and only writing them as Android logs if the span fails.
+///
+/// A span fails when an `ERROR` event occurs within it or when it records a field named `error`,
+/// either when it is created or later on. The events kept for the span and for all its parents are then written,
+/// from the oldest to the newest, and the following events of these spans are written right away.
+/// The events of spans closed without failing are discarded,
+/// and each span only keeps its last events, up to a [capacity](Self::with_capacity).
+///
+/// Only events at or below the [level](Self::with_level) occurring within a span are handled by this layer,
+/// so it is meant to be composed with another layer writing the rest of the events.
+///
+/// ```rust
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory, Priority, SpanBufferLayer};
+/// use tracing::field;
+/// use tracing_subscriber::{filter::LevelFilter, prelude::*};
+///
+/// let memory = Memory::new();
+/// let make_writer = |memory: &Memory| AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone());
+/// let subscriber = tracing_subscriber::registry()
+///     .with(SpanBufferLayer::new(make_writer(&memory)))
+///     .with(paranoid_android::layer("app").with_writer(make_writer(&memory)).with_filter(LevelFilter::INFO));
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::info_span!("request", id = 1).in_scope(|| {
+///         tracing::debug!("parsing");
+///     });
+///     tracing::info_span!("request", id = 2).in_scope(|| {
+///         tracing::debug!("parsing");
+///         tracing::error!("failed");
+///         tracing::debug!("cleaning up");
+///     });
+///     let span = tracing::info_span!("request", id = 3, error = field::Empty);
+///     span.in_scope(|| tracing::trace!("sending"));
+///     span.record("error", "timed out");
+/// });
+///
+/// let records = memory.take();
+/// let records: Vec<_> = records.iter().map(|r| (r.priority, r.message.as_str())).collect();
+/// assert_eq!(records.len(), 4);
+/// assert_eq!(records[0].0, Priority::Debug);
+/// assert!(records[0].1.starts_with("request{id=2}:") && records[0].1.ends_with("parsing\n"));
+/// assert_eq!(records[1].0, Priority::Error);
+/// assert!(records[1].1.ends_with("failed\n"));
+/// assert!(records[2].1.ends_with("cleaning up\n"));
+/// assert_eq!(records[3].0, Priority::Verbose);
+/// assert!(records[3].1.starts_with("request{id=3}:") && records[3].1.ends_with("sending\n"));
+/// ```
+pub struct SpanBufferLayer<S, B: Backend = DefaultBackend> {
+    fmt:
+        tracing_subscriber::fmt::Layer<S, ReservedFields<SpanFields>, Format<Full, ()>, Capture<B>>,
+    level: Level,
+    capacity: usize,
+    dropped: Counter,
+    next_seq: AtomicU64,
+}
+
+/// The field formatter of the events kept, distinct from the one of the other layers of this crate
+/// so that the fields of spans formatted for them aren't formatted again.
+#[derive(Debug, Default)]
+struct SpanFields(DefaultFields);
+
+/// A [`MakeWriter`] capturing formatted events so that they can be moved to their span.
+#[derive(Debug)]
+struct Capture<B: Backend>(AndroidLogMakeWriter<B>);
+
+struct CaptureWriter(Option<BufferedRecord>);
+
+/// The events kept for a span, stored in its extensions.
+struct Buffered {
+    failed: bool,
+    entries: VecDeque<BufferedRecord>,
+}
+
+/// A visitor looking for the `error` field.
+struct ErrorVisitor(bool);
+
+thread_local! {
+    /// The last event captured on this thread.
+    static CAPTURED: RefCell<Option<BufferedRecord>> = const { RefCell::new(None) };
+}
+
+const ERROR_FIELD: &str = "error";
+const DEFAULT_CAPACITY: usize = 256;
+
+impl<S, B> SpanBufferLayer<S, B>
+where
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+    B: Backend + 'static,
+{
+    /// Returns a new [`SpanBufferLayer`] writing through the given [`AndroidLogMakeWriter`].
+    pub fn new(make_writer: AndroidLogMakeWriter<B>) -> Self {
+        Self {
+            fmt: tracing_subscriber::fmt::Layer::new()
+                .fmt_fields(ReservedFields::new(SpanFields::default()))
+                .event_format(Format::default().with_level(false).without_time())
+                .with_writer(Capture(make_writer)),
+            level: Level::DEBUG,
+            capacity: DEFAULT_CAPACITY,
+            dropped: Default::default(),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<S, B: Backend> SpanBufferLayer<S, B> {
+    /// Sets the least verbose level of the events kept. Defaults to `DEBUG`, which keeps `DEBUG` and `TRACE` events.
+    ///
+    /// `ERROR` events are never kept, since they make their span fail.
+    pub fn with_level(self, level: Level) -> Self {
+        Self { level, ..self }
+    }
+
+    /// Sets the maximum number of events kept for each span. Defaults to 256.
+    ///
+    /// Once a span keeps that many events, the oldest ones are dropped to make room for the new ones
+    /// and counted by [`dropped_count`](Self::dropped_count).
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        Self { capacity, ..self }
+    }
+
+    /// Returns a [`Counter`] of the events dropped because their span kept too many of them.
+    pub fn dropped_count(&self) -> Counter {
+        self.dropped.clone()
+    }
+
+    /// Marks the given span and its parents as failed and writes out their events.
+    fn fail(&self, span: &SpanRef<'_, S>)
+    where
+        S: for<'a> LookupSpan<'a>,
+    {
+        let mut entries = Vec::new();
+        for span in span.scope() {
+            if let Some(buffered) = span.extensions_mut().get_mut::<Buffered>() {
+                buffered.failed = true;
+                entries.extend(buffered.entries.drain(..));
+            }
+        }
+
+        entries.sort_by_key(|entry| entry.seq);
+        for entry in entries {
+            self.write(entry);
+        }
+    }
+
+    fn write(&self, entry: BufferedRecord) {
+        self.fmt.writer().0.replay(entry)
+    }
+}
+
+impl<S, B> tracing_subscriber::Layer<S> for SpanBufferLayer<S, B>
+where
+    S: Subscriber,
+    for<'a> S: LookupSpan<'a>,
+    B: Backend + 'static,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.fmt.on_new_span(attrs, id, ctx.clone());
+
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let failed = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<Buffered>().map(|b| b.failed))
+            .unwrap_or(false);
+        span.extensions_mut().insert(Buffered {
+            failed,
+            entries: VecDeque::new(),
+        });
+
+        if ErrorVisitor::find(|visitor| attrs.record(visitor)) {
+            self.fail(&span);
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.fmt.on_record(id, values, ctx.clone());
+
+        if ErrorVisitor::find(|visitor| values.record(visitor)) {
+            if let Some(span) = ctx.span(id) {
+                self.fail(&span);
+            }
+        }
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.fmt.on_follows_from(id, follows, ctx)
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        let span = match ctx.event_span(event) {
+            Some(span) => span,
+            None => return,
+        };
+        if level == Level::ERROR {
+            self.fail(&span);
+            return;
+        }
+        if level < self.level {
+            return;
+        }
+
+        self.fmt.on_event(event, ctx.clone());
+        let mut entry = match CAPTURED.with(|captured| captured.borrow_mut().take()) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let mut extensions = span.extensions_mut();
+        match extensions.get_mut::<Buffered>() {
+            Some(buffered) if !buffered.failed => {
+                if self.capacity == 0 {
+                    self.dropped.increment();
+                    return;
+                }
+                if buffered.entries.len() >= self.capacity {
+                    buffered.entries.pop_front();
+                    self.dropped.increment();
+                }
+                entry.seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+                buffered.entries.push_back(entry);
+            }
+            _ => {
+                drop(extensions);
+                self.write(entry);
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.fmt.on_enter(id, ctx)
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.fmt.on_exit(id, ctx)
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.fmt.on_close(id, ctx)
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.fmt.on_id_change(old, new, ctx)
+    }
+}
+
+impl<S, B: Backend + fmt::Debug> fmt::Debug for SpanBufferLayer<S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpanBufferLayer")
+            .field("make_writer", &self.fmt.writer().0)
+            .field("level", &self.level)
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> MakeVisitor<Writer<'a>> for SpanFields {
+    type Visitor = <DefaultFields as MakeVisitor<Writer<'a>>>::Visitor;
+
+    fn make_visitor(&self, target: Writer<'a>) -> Self::Visitor {
+        self.0.make_visitor(target)
+    }
+}
+
+impl<'a, B: Backend + 'a> MakeWriter<'a> for Capture<B> {
+    type Writer = CaptureWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        CaptureWriter(None)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        CaptureWriter(self.0.buffered(meta))
+    }
+}
+
+impl Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(entry) = &mut self.0 {
+            entry.message.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        if let Some(entry) = self.0.take() {
+            CAPTURED.with(|captured| *captured.borrow_mut() = Some(entry));
+        }
+    }
+}
+
+impl ErrorVisitor {
+    /// Returns whether the `error` field is among the values recorded by the given function.
+    fn find(record: impl FnOnce(&mut Self)) -> bool {
+        let mut visitor = Self(false);
+        record(&mut visitor);
+        visitor.0
+    }
+}
+
+impl Visit for ErrorVisitor {
+    fn record_debug(&mut self, field: &Field, _: &dyn fmt::Debug) {
+        self.0 |= field.name() == ERROR_FIELD;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::field;
+    use tracing_subscriber::{filter::LevelFilter, prelude::*};
+
+    use super::*;
+    use crate::{Buffer, Memory};
+
+    #[test]
+    fn spans_keep_their_last_events_up_to_the_capacity() {
+        let memory = Memory::new();
+        let make_writer =
+            AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone());
+        let layer = SpanBufferLayer::new(make_writer).with_capacity(2);
+        let dropped = layer.dropped_count();
+
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            tracing::info_span!("worker").in_scope(|| {
+                for i in 0..5 {
+                    tracing::debug!(i, "working");
+                }
+                tracing::error!("failed");
+            });
+        });
+
+        let messages: Vec<_> = memory.take().into_iter().map(|r| r.message).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].ends_with("working i=3\n"));
+        assert!(messages[1].ends_with("working i=4\n"));
+        assert_eq!(dropped.get(), 3);
+    }
+
+    #[test]
+    fn fields_recorded_later_are_formatted_once() {
+        let memory = Memory::new();
+        let make_writer =
+            || AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone());
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanBufferLayer::new(make_writer()))
+            .with(
+                crate::layer("app")
+                    .with_target(false)
+                    .with_writer(make_writer())
+                    .with_filter(LevelFilter::INFO),
+            );
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("req", a = field::Empty);
+            span.record("a", 1);
+            span.in_scope(|| {
+                tracing::debug!("probe");
+                tracing::error!("failed");
+            });
+        });
+
+        let messages: Vec<_> = memory.take().into_iter().map(|r| r.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "req{a=1}: paranoid_android::span_buffer::tests: probe\n",
+                "req{a=1}: failed\n",
+            ]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use tracing_core::{callsite::Identifier, Metadata};
+
+use crate::sync::{read, write};
+
+/// How [`AndroidLogMakeWriter`](crate::AndroidLogMakeWriter) derives the tag of a record
+/// from the [target](Metadata::target) of the event it originates from.
+///
+/// Tags are computed once per callsite and cached.
+/// Whenever a tag can't be derived, or contains an interior NUL byte,
+/// the tag given to the writer is used instead.
+///
+/// ```rust
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory, TagStrategy};
+/// use tracing_subscriber::prelude::*;
+///
+/// let tags = |strategy| {
+///     let memory = Memory::new();
+///     let make_writer = AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone())
+///         .with_tag_strategy(strategy);
+///     let subscriber = tracing_subscriber::registry()
+///         .with(tracing_subscriber::fmt::layer().without_time().with_writer(make_writer));
+///
+///     tracing::subscriber::with_default(subscriber, || {
+///         tracing::info!(target: "my_app::network::http", "request");
+///         tracing::info!(target: "other", "unrelated");
+///     });
+///     memory.take().into_iter().map(|r| r.tag).collect::<Vec<_>>()
+/// };
+///
+/// assert_eq!(tags(TagStrategy::Fixed), ["app", "app"]);
+/// assert_eq!(tags(TagStrategy::Target), ["my_app::network::http", "other"]);
+/// assert_eq!(tags(TagStrategy::CrateName), ["my_app", "other"]);
+/// assert_eq!(tags(TagStrategy::LastSegment), ["http", "other"]);
+/// assert_eq!(
+///     tags(TagStrategy::map(|target| target.strip_prefix("my_app::").map(str::to_uppercase))),
+///     ["NETWORK::HTTP", "app"],
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub enum TagStrategy {
+    /// Always use the tag given to the writer.
+    #[default]
+    Fixed,
+    /// Use the full target, like `my_app::network::http`.
+    Target,
+    /// Use the first segment of the target, which is the crate name by default, like `my_app`.
+    CrateName,
+    /// Use the last segment of the target, like `http`.
+    LastSegment,
+    /// Use the tag returned by the given function, if any.
+    #[allow(clippy::type_complexity)]
+    Map(Arc<dyn Fn(&str) -> Option<String> + Send + Sync>),
+}
+
+/// A per-callsite cache of the tags derived using a [`TagStrategy`].
+#[derive(Debug)]
+pub(crate) struct Tags {
+    fallback: Arc<CStr>,
+    strategy: TagStrategy,
+    cache: RwLock<HashMap<Identifier, Arc<CStr>>>,
+}
+
+impl TagStrategy {
+    /// Returns a [`TagStrategy::Map`] using the given function.
+    pub fn map<F>(f: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        TagStrategy::Map(Arc::new(f))
+    }
+
+    /// Returns the tag derived from the given target, if any.
+    pub(crate) fn derive(&self, target: &str) -> Option<String> {
+        match self {
+            TagStrategy::Fixed => None,
+            TagStrategy::Target => Some(target.to_owned()),
+            TagStrategy::CrateName => target.split("::").next().map(str::to_owned),
+            TagStrategy::LastSegment => target.rsplit("::").next().map(str::to_owned),
+            TagStrategy::Map(f) => f(target),
+        }
+    }
+}
+
+impl fmt::Debug for TagStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagStrategy::Fixed => f.write_str("Fixed"),
+            TagStrategy::Target => f.write_str("Target"),
+            TagStrategy::CrateName => f.write_str("CrateName"),
+            TagStrategy::LastSegment => f.write_str("LastSegment"),
+            TagStrategy::Map(_) => f.debug_tuple("Map").field(&..).finish(),
+        }
+    }
+}
+
+impl Tags {
+    pub(crate) fn new(fallback: CString) -> Self {
+        Self {
+            fallback: fallback.into(),
+            strategy: TagStrategy::Fixed,
+            cache: Default::default(),
+        }
+    }
+
+    pub(crate) fn with_strategy(self, strategy: TagStrategy) -> Self {
+        Self {
+            strategy,
+            cache: Default::default(),
+            ..self
+        }
+    }
+
+    /// Returns the tag given to the writer.
+    pub(crate) fn fallback(&self) -> &Arc<CStr> {
+        &self.fallback
+    }
+
+    /// Returns the tag of records originating from the callsite with the given metadata.
+    pub(crate) fn get(&self, meta: &Metadata<'_>) -> Arc<CStr> {
+        if let TagStrategy::Fixed = self.strategy {
+            return self.fallback.clone();
+        }
+
+        let callsite = meta.callsite();
+        if let Some(tag) = read(&self.cache).get(&callsite) {
+            return tag.clone();
+        }
+
+        let tag = self
+            .strategy
+            .derive(meta.target())
+            .and_then(|tag| CString::new(tag).ok())
+            .map_or_else(|| self.fallback.clone(), Arc::from);
+        write(&self.cache).insert(callsite, tag.clone());
+        tag
+    }
+}
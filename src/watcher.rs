@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use tracing_core::callsite;
+
+use crate::{filter::State, property::PropertySource, sync::lock};
+
+/// Reloads a [`PropertyFilter`](crate::PropertyFilter) when the `log.tag.*` and `persist.log.tag.*` properties it depends on change,
+/// so that `adb shell setprop log.tag.MyTag V` takes effect without restarting the app.
+///
+/// Android doesn't notify processes of property changes, so they have to be [polled](Self::poll),
+/// either manually or from a [background thread](Self::spawn).
+/// Only the properties of the tags the filter has already seen are polled.
+///
+/// ```rust
+/// use std::{
+///     collections::HashMap,
+///     sync::{Arc, Mutex},
+/// };
+///
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory, PropertyFilter};
+/// use tracing_subscriber::prelude::*;
+///
+/// let properties = Arc::new(Mutex::new(HashMap::new()));
+/// let filter = PropertyFilter::new("app").with_properties({
+///     let properties = properties.clone();
+///     move |name: &str| properties.lock().unwrap().get(name).cloned()
+/// });
+/// let watcher = filter.watcher();
+///
+/// let memory = Memory::new();
+/// let make_writer = AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone());
+/// let subscriber = tracing_subscriber::registry()
+///     .with(tracing_subscriber::fmt::layer().with_writer(make_writer).with_filter(filter));
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     let debug = || tracing::debug!("details");
+///
+///     debug();
+///     assert!(!watcher.poll());
+///     assert!(memory.take().is_empty());
+///
+///     properties.lock().unwrap().insert("log.tag.app".to_owned(), "V".to_owned());
+///     assert!(watcher.poll());
+///     debug();
+///     assert_eq!(memory.take().len(), 1);
+/// });
+/// ```
+pub struct PropertyWatcher {
+    source: Arc<dyn PropertySource>,
+    state: Arc<State>,
+    values: Mutex<HashMap<String, Option<String>>>,
+    callback: Option<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl PropertyWatcher {
+    pub(crate) fn new(source: Arc<dyn PropertySource>, state: Arc<State>) -> Self {
+        Self {
+            source,
+            state,
+            values: Default::default(),
+            callback: None,
+        }
+    }
+
+    /// Sets a callback invoked every time the filter is reloaded.
+    pub fn with_callback(self, callback: impl Fn() + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Some(Box::new(callback)),
+            ..self
+        }
+    }
+
+    /// Polls the properties once, reloading the filter and returning `true` if any of them changed.
+    pub fn poll(&self) -> bool {
+        let mut values = lock(&self.values);
+
+        let mut changed = false;
+        for tag in self.state.tags() {
+            for name in crate::property::names(&tag) {
+                let value = self.source.get(&name);
+                let previous = values.entry(name).or_default();
+                if *previous != value {
+                    *previous = value;
+                    changed = true;
+                }
+            }
+        }
+        drop(values);
+
+        if changed {
+            self.state.clear();
+            callsite::rebuild_interest_cache();
+            if let Some(callback) = &self.callback {
+                callback();
+            }
+        }
+        changed
+    }
+
+    /// Spawns a thread polling the properties at the given interval for the rest of the life of the process.
+    pub fn spawn(self, interval: Duration) -> std::io::Result<thread::JoinHandle<()>> {
+        thread::Builder::new()
+            .name("log-tag-watcher".to_owned())
+            .spawn(move || loop {
+                self.poll();
+                thread::sleep(interval);
+            })
+    }
+}
+
+impl fmt::Debug for PropertyWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PropertyWatcher")
+            .field("state", &self.state)
+            .field("values", &self.values)
+            .field("callback", &self.callback.as_ref().map(|_| ..))
+            .finish()
+    }
+}
@@ -1,154 +1,327 @@
 use core::slice;
 use std::{
     ffi::{CStr, CString},
+    fmt,
     io::{self, Write},
-    os::raw::c_char,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 use lazy_static::lazy_static;
 use sharded_slab::{pool::RefMut, Pool};
-use smallvec::SmallVec;
+use smallvec::{smallvec, SmallVec};
 use tracing_core::Metadata;
 use tracing_subscriber::fmt::MakeWriter;
 
-use crate::logging::{Buffer, Priority};
+use crate::{
+    backend::{Backend, DefaultBackend, Record},
+    chunk::{chunks, truncate, Marker},
+    crash::abort_message,
+    fields::Overrides,
+    logging::{Buffer, Priority},
+    tag::{TagStrategy, Tags},
+    Error,
+};
 
 /// The writer produced by [`AndroidLogMakeWriter`].
 #[derive(Debug)]
-pub struct AndroidLogWriter<'a> {
-    tag: &'a CStr,
-    message: PooledCString,
+pub struct AndroidLogWriter<'a, B: Backend = DefaultBackend> {
+    tag: Arc<CStr>,
+    make_writer: &'a AndroidLogMakeWriter<B>,
+    message: Option<PooledCString>,
 
-    priority: Priority,
+    /// The priority of the records, or `None` if they are dropped.
+    priority: Option<Priority>,
     buffer: Buffer,
     location: Option<Location>,
 }
 
 /// A [`MakeWriter`] suitable for writing Android logs.
 #[derive(Debug)]
-pub struct AndroidLogMakeWriter {
-    tag: CString,
+pub struct AndroidLogMakeWriter<B: Backend = DefaultBackend> {
+    tags: Tags,
+    buffer: Buffer,
+    backend: Arc<B>,
+    errors: ErrorHandler,
+    nul_policy: NulPolicy,
+    chunk_markers: bool,
+    next_chunked_id: AtomicU32,
+    max_len: Option<usize>,
+    overflow: Overflow,
+    dropped: Counter,
+    priority_mapping: PriorityMapping,
+    location: bool,
+    fatal_policy: FatalPolicy,
+}
+
+/// What [`AndroidLogWriter`] does with messages longer than the maximum record length.
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory, Overflow};
+/// use tracing_subscriber::fmt::MakeWriter;
+///
+/// let memory = Memory::new();
+/// let write = |overflow| {
+///     let make_writer = AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Main, memory.clone())
+///         .with_max_len(8)
+///         .with_overflow(overflow);
+///     make_writer.make_writer().write_all(b"one\ntwo\nthree\nfour").unwrap();
+///     let messages: Vec<_> = memory.take().into_iter().map(|r| r.message).collect();
+///     (messages, make_writer.dropped_count().get())
+/// };
+///
+/// assert_eq!(write(Overflow::Split), (vec!["one\ntwo\n".to_owned(), "three\n".to_owned(), "four".to_owned()], 0));
+/// assert_eq!(write(Overflow::Truncate), (vec!["one\nt…".to_owned()], 0));
+/// assert_eq!(write(Overflow::SplitAtMost(2)), (vec!["one\ntwo\n".to_owned(), "three\n".to_owned()], 1));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Split messages into as many records as needed.
+    #[default]
+    Split,
+    /// Truncate messages to a single record, ending with an ellipsis.
+    Truncate,
+    /// Split messages into at most the given number of records, dropping the rest.
+    ///
+    /// Dropped records are counted by [`AndroidLogMakeWriter::dropped_count`].
+    SplitAtMost(usize),
+}
+
+/// How [`AndroidLogWriter`] handles interior NUL bytes in messages,
+/// which can't be represented in Android logs.
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory, NulPolicy};
+/// use tracing_subscriber::fmt::MakeWriter;
+///
+/// let write = |nul_policy| {
+///     let memory = Memory::new();
+///     let make_writer = AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Main, memory.clone())
+///         .with_nul_policy(nul_policy);
+///     make_writer.make_writer().write_all(b"a\0b\0").unwrap();
+///     memory.take().into_iter().map(|r| r.message).collect::<Vec<_>>()
+/// };
+///
+/// assert_eq!(write(NulPolicy::Escape), ["a\\0b\\0"]);
+/// assert_eq!(write(NulPolicy::Replace), ["a\u{FFFD}b\u{FFFD}"]);
+/// assert_eq!(write(NulPolicy::Split), ["a", "b"]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NulPolicy {
+    /// Replace NUL bytes with the `\0` escape sequence.
+    #[default]
+    Escape,
+    /// Replace NUL bytes with the U+FFFD replacement character.
+    Replace,
+    /// Split messages at NUL bytes, writing each part as its own record.
+    Split,
+}
+
+/// What [`AndroidLogWriter`] does with records at the [fatal](Priority::Fatal) priority, besides writing them.
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// use paranoid_android::{AndroidLogMakeWriter, Buffer, FatalPolicy, Memory, Priority};
+/// use tracing_subscriber::prelude::*;
+///
+/// let memory = Memory::new();
+/// let make_writer = AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone())
+///     .with_fatal_policy(FatalPolicy::SetAbortMessage);
+/// let subscriber = tracing_subscriber::registry()
+///     .with(paranoid_android::layer::<_>("app").with_writer(make_writer));
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::error!("recoverable");
+///     assert_eq!(memory.abort_message(), None);
+///
+///     tracing::error!(android.priority = "fatal", "unrecoverable");
+///     assert!(memory.abort_message().unwrap().ends_with("unrecoverable"));
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FatalPolicy {
+    /// Only write the record.
+    #[default]
+    Log,
+    /// Also set the formatted message as the [abort message](Backend::set_abort_message) of the process,
+    /// truncated to a reasonable length, so that it shows up in the tombstone written when the process aborts.
+    SetAbortMessage,
+}
+
+/// A shared counter, incremented by an [`AndroidLogMakeWriter`] and readable from anywhere.
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicUsize>);
+
+/// A formatted record kept in memory to be written later, by a [`FlightRecorder`](crate::FlightRecorder)
+/// or a [`SpanBufferLayer`](crate::SpanBufferLayer).
+#[derive(Debug)]
+pub(crate) struct BufferedRecord {
+    /// The order in which the record was kept, among the records kept together.
+    pub(crate) seq: u64,
+    pub(crate) message: Vec<u8>,
+    tag: Arc<CStr>,
     buffer: Buffer,
+    priority: Priority,
+}
+
+/// The errors reported by a writer or a layer: counted, and passed to an optional callback.
+#[derive(Default)]
+pub(crate) struct ErrorHandler {
+    pub(crate) count: Counter,
+    pub(crate) callback: Option<ErrorCallback>,
 }
 
+pub(crate) type ErrorCallback = Box<dyn Fn(&io::Error) + Send + Sync>;
+
+#[derive(Default)]
+pub(crate) struct PriorityMapping(Option<MappingFn>);
+
+type MappingFn = Box<dyn Fn(&Metadata<'_>) -> Option<Priority> + Send + Sync>;
+
 #[derive(Debug)]
 struct Location {
     file: PooledCString,
     line: u32,
 }
 
-// logd truncates logs at 4096 bytes, so we chunk at 4000 to be conservative
-const MAX_LOG_LEN: usize = 4000;
+/// The maximum payload size of a log entry accepted by logd,
+/// which includes the priority, the tag and the message along with their NUL terminators.
+const LOGGER_ENTRY_MAX_PAYLOAD: usize = 4068;
+
+const ELLIPSIS: &str = "…";
 
-impl Write for AndroidLogWriter<'_> {
+/// The smallest maximum length of a message, leaving room for at least one byte besides the ellipsis.
+const MIN_MAX_LEN: usize = ELLIPSIS.len() + 1;
+
+impl<B: Backend> Write for AndroidLogWriter<'_, B> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.message.write(buf);
-        Ok(buf.len())
+        match &mut self.message {
+            _ if self.priority.is_none() => Ok(buf.len()),
+            Some(message) => {
+                message.write(buf);
+                Ok(buf.len())
+            }
+            None => {
+                let e = Error::PoolExhausted.into();
+                self.make_writer.errors.report(&e);
+                Err(e)
+            }
+        }
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        let mut sv = SmallVec::<[PooledCString; 4]>::new();
-        let messages = if self.message.as_bytes().len() < MAX_LOG_LEN {
-            MessageIter::Single(Some(&mut self.message))
-        } else {
-            sv.extend(
-                self.message
-                    .as_bytes()
-                    .chunks(MAX_LOG_LEN)
-                    .map(PooledCString::new),
-            );
-            MessageIter::Multi(sv.as_mut().iter_mut())
+        let result = self.write_records();
+        if let Err(e) = &result {
+            self.make_writer.errors.report(e);
         }
-        .filter_map(PooledCString::as_ptr);
-
-        let buffer = self.buffer.as_raw().0 as i32;
-        let priority = self.priority.as_raw().0 as i32;
-        let tag = self.tag.as_ptr();
+        result
+    }
+}
 
-        #[cfg(feature = "api-30")]
+impl<B: Backend> AndroidLogWriter<'_, B> {
+    fn write_records(&mut self) -> io::Result<()> {
+        let backend = &self.make_writer.backend;
+        let (priority, message) = match (self.priority, &mut self.message) {
+            (Some(priority), Some(message)) if !message.as_bytes().is_empty() => {
+                (priority, message)
+            }
+            _ => return Ok(()),
+        };
+        if priority == Priority::Fatal
+            && self.make_writer.fatal_policy == FatalPolicy::SetAbortMessage
         {
-            use std::{mem::size_of, ptr::null};
+            // backends without abort messages have nothing else to do
+            let _ = backend.set_abort_message(&abort_message(message.as_bytes()));
+        }
+        if !backend.is_loggable(priority, &self.tag) {
+            message.clear();
+            return Ok(());
+        }
+        #[cfg(unix)]
+        crate::signal::remember(priority, message.as_bytes());
 
-            use ndk_sys::{
-                __android_log_is_loggable, __android_log_message, __android_log_write_log_message,
+        let mut sv: SmallVec<[PooledCString; 4]>;
+        let bytes = message.as_bytes();
+        let max_len = self.make_writer.max_len(&self.tag);
+        let messages = if bytes.len() <= max_len && !bytes.contains(&0) {
+            MessageIter::Single(Some(&mut *message))
+        } else {
+            sv = match self.make_writer.pieces(bytes, max_len) {
+                Ok(pieces) => pieces,
+                Err(e) => {
+                    message.clear();
+                    return Err(e.into());
+                }
             };
+            MessageIter::Multi(sv.as_mut().iter_mut())
+        }
+        .filter_map(PooledCString::as_c_str);
 
-            if unsafe { __android_log_is_loggable(priority, tag, priority) } == 0 {
-                return Ok(());
-            }
+        let (file, line) = match &mut self.location {
+            Some(Location { file, line }) => (file.as_c_str(), Some(*line)),
+            None => (None, None),
+        };
 
-            let (file, line) = match &mut self.location {
-                Some(Location { file, line }) => match file.as_ptr() {
-                    Some(ptr) => (ptr, *line),
-                    None => (null(), 0),
-                },
-                None => (null(), 0),
+        let mut result = Ok(());
+        for message in messages {
+            let record = Record {
+                buffer: self.buffer,
+                priority,
+                tag: &self.tag,
+                file,
+                line,
+                message,
             };
 
-            for message in messages {
-                let mut message = __android_log_message {
-                    struct_size: size_of::<__android_log_message>(),
-                    buffer_id: buffer,
-                    priority,
-                    tag,
-                    file,
-                    line,
-                    message,
-                };
-
-                unsafe { __android_log_write_log_message(&mut message) };
+            if let Err(e) = backend.write(&record) {
+                result = Err(e);
             }
         }
 
-        #[cfg(not(feature = "api-30"))]
-        {
-            use ndk_sys::__android_log_buf_write;
-
-            for message in messages {
-                unsafe { __android_log_buf_write(buffer, priority, tag, message) };
-            }
+        if let Some(message) = &mut self.message {
+            message.clear();
         }
-
-        Ok(())
+        result
     }
 }
 
-impl Drop for AndroidLogWriter<'_> {
+impl<B: Backend> Drop for AndroidLogWriter<'_, B> {
     fn drop(&mut self) {
-        self.flush().unwrap();
+        // failures are reported to the error handler by `flush`
+        let _ = self.flush();
     }
 }
 
-impl<'a> MakeWriter<'a> for AndroidLogMakeWriter {
-    type Writer = AndroidLogWriter<'a>;
+impl<'a, B: Backend + 'a> MakeWriter<'a> for AndroidLogMakeWriter<B> {
+    type Writer = AndroidLogWriter<'a, B>;
 
     fn make_writer(&'a self) -> Self::Writer {
-        AndroidLogWriter {
-            tag: self.tag.as_c_str(),
-            message: PooledCString::empty(),
-
-            buffer: self.buffer,
-            priority: Priority::Info,
-            location: None,
-        }
+        self.writer(self.buffer, Priority::Info)
     }
 
     fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
-        let priority = (*meta.level()).into();
-
+        let (tag, buffer, priority) = self.resolve(meta);
         let location = match (meta.file(), meta.line()) {
-            (Some(file), Some(line)) => {
-                let file = PooledCString::new(file.as_bytes());
-                Some(Location { file, line })
-            }
+            _ if priority.is_none() || !self.location => None,
+            (Some(file), Some(line)) => PooledCString::new(file.as_bytes())
+                .ok()
+                .map(|file| Location { file, line }),
             _ => None,
         };
 
         AndroidLogWriter {
-            tag: self.tag.as_c_str(),
-            message: PooledCString::empty(),
+            tag,
+            make_writer: self,
+            message: priority.and_then(|_| PooledCString::empty().ok()),
 
-            buffer: self.buffer,
+            buffer,
             priority,
             location,
         }
@@ -157,20 +330,502 @@ impl<'a> MakeWriter<'a> for AndroidLogMakeWriter {
 
 impl AndroidLogMakeWriter {
     /// Returns a new [`AndroidLogWriter`] with the given tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains an interior NUL byte. See [`try_new`](Self::try_new) for a fallible version.
     pub fn new(tag: String) -> Self {
         Self::with_buffer(tag, Default::default())
     }
 
     /// Returns a new [`AndroidLogMakeWriter`] with the given tag and using the
     /// given [Android log buffer](Buffer).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains an interior NUL byte. See [`try_with_buffer`](Self::try_with_buffer) for a fallible version.
     pub fn with_buffer(tag: String, buffer: Buffer) -> Self {
+        Self::with_backend(tag, buffer, Default::default())
+    }
+
+    /// Returns a new [`AndroidLogWriter`] with the given tag,
+    /// or an error if the tag contains an interior NUL byte.
+    pub fn try_new(tag: String) -> Result<Self, Error> {
+        Self::try_with_buffer(tag, Default::default())
+    }
+
+    /// Returns a new [`AndroidLogMakeWriter`] with the given tag and using the
+    /// given [Android log buffer](Buffer), or an error if the tag contains an interior NUL byte.
+    pub fn try_with_buffer(tag: String, buffer: Buffer) -> Result<Self, Error> {
+        Self::try_with_backend(tag, buffer, Default::default())
+    }
+}
+
+impl<B: Backend> AndroidLogMakeWriter<B> {
+    /// Returns a new [`AndroidLogMakeWriter`] with the given tag, using the
+    /// given [Android log buffer](Buffer) and writing to the given [`Backend`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains an interior NUL byte. See [`try_with_backend`](Self::try_with_backend) for a fallible version.
+    pub fn with_backend(tag: String, buffer: Buffer, backend: B) -> Self {
+        Self::try_with_backend(tag, buffer, backend).unwrap()
+    }
+
+    /// Returns a new [`AndroidLogMakeWriter`] with the given tag, using the
+    /// given [Android log buffer](Buffer) and writing to the given [`Backend`],
+    /// or an error if the tag contains an interior NUL byte.
+    pub fn try_with_backend(tag: String, buffer: Buffer, backend: B) -> Result<Self, Error> {
+        Ok(Self {
+            tags: Tags::new(CString::new(tag)?),
+            buffer,
+            backend: Arc::new(backend),
+            errors: Default::default(),
+            nul_policy: Default::default(),
+            chunk_markers: false,
+            next_chunked_id: AtomicU32::new(0),
+            max_len: None,
+            overflow: Default::default(),
+            dropped: Default::default(),
+            priority_mapping: Default::default(),
+            location: true,
+            fatal_policy: Default::default(),
+        })
+    }
+
+    /// Sets how the tag of each record is derived from its target. Defaults to [`TagStrategy::Fixed`].
+    pub fn with_tag_strategy(self, strategy: TagStrategy) -> Self {
+        Self {
+            tags: self.tags.with_strategy(strategy),
+            ..self
+        }
+    }
+
+    /// Sets how interior NUL bytes in messages are handled. Defaults to [`NulPolicy::Escape`].
+    pub fn with_nul_policy(self, nul_policy: NulPolicy) -> Self {
+        Self { nul_policy, ..self }
+    }
+
+    /// Sets whether messages split into several records are marked as such. Defaults to `false`.
+    ///
+    /// When enabled, each record of a split message is prefixed with a marker like `[2/5 #1f] `,
+    /// holding its position, the total number of records and an ID shared by all the records of the message.
+    /// With [`NulPolicy::Split`], each part of the message is marked on its own.
+    /// Such records can be stitched back together using [`reassemble`](crate::reassemble).
+    ///
+    /// ```rust
+    /// use std::io::Write;
+    ///
+    /// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory};
+    /// use tracing_subscriber::fmt::MakeWriter;
+    ///
+    /// let memory = Memory::new();
+    /// let make_writer = AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Main, memory.clone())
+    ///     .with_chunk_markers(true);
+    ///
+    /// let message = "line\n".repeat(2000);
+    /// make_writer.make_writer().write_all(message.as_bytes()).unwrap();
+    ///
+    /// let records: Vec<_> = memory.take().into_iter().map(|r| r.message).collect();
+    /// assert_eq!(records.len(), 3);
+    /// assert!(records[0].starts_with("[1/3 #0] line\n"));
+    /// assert!(records[2].starts_with("[3/3 #0] line\n"));
+    ///
+    /// assert_eq!(paranoid_android::reassemble(&records), [message]);
+    /// ```
+    pub fn with_chunk_markers(self, chunk_markers: bool) -> Self {
         Self {
-            tag: CString::new(tag).unwrap(),
+            chunk_markers,
+            ..self
+        }
+    }
+
+    /// Sets the maximum length of the message of a single record, in bytes.
+    ///
+    /// Defaults to the exact limit enforced by logd, which depends on the length of the tag.
+    pub fn with_max_len(self, max_len: usize) -> Self {
+        Self {
+            max_len: Some(max_len.max(MIN_MAX_LEN)),
+            ..self
+        }
+    }
+
+    /// Sets what to do with messages longer than the [maximum length](Self::with_max_len).
+    /// Defaults to [`Overflow::Split`].
+    pub fn with_overflow(self, overflow: Overflow) -> Self {
+        Self { overflow, ..self }
+    }
+
+    /// Sets the function mapping the metadata of each event to the [`Priority`] of its records,
+    /// or to `None` to drop the event entirely.
+    ///
+    /// Defaults to converting the [level](Metadata::level) of the event into a [`Priority`].
+    /// A priority set through the `android.priority` [reserved field](crate::ReservedFields) takes precedence over the mapping.
+    ///
+    /// ```rust
+    /// use paranoid_android::{AndroidLogMakeWriter, Buffer, Memory, Priority};
+    /// use tracing::Level;
+    /// use tracing_subscriber::prelude::*;
+    ///
+    /// let memory = Memory::new();
+    /// let make_writer = AndroidLogMakeWriter::with_backend("app".to_owned(), Buffer::Main, memory.clone())
+    ///     .with_priority_mapping(|meta| match *meta.level() {
+    ///         Level::TRACE => None,
+    ///         Level::ERROR if meta.fields().field("fatal").is_some() => Some(Priority::Fatal),
+    ///         level => Some(level.into()),
+    ///     });
+    /// let subscriber = tracing_subscriber::registry()
+    ///     .with(tracing_subscriber::fmt::layer().without_time().with_writer(make_writer));
+    ///
+    /// tracing::subscriber::with_default(subscriber, || {
+    ///     tracing::trace!("dropped");
+    ///     tracing::error!(fatal = true, "unrecoverable");
+    ///     tracing::error!("recoverable");
+    /// });
+    ///
+    /// let priorities: Vec<_> = memory.take().into_iter().map(|r| r.priority).collect();
+    /// assert_eq!(priorities, [Priority::Fatal, Priority::Error]);
+    /// ```
+    pub fn with_priority_mapping<F>(self, mapping: F) -> Self
+    where
+        F: Fn(&Metadata<'_>) -> Option<Priority> + Send + Sync + 'static,
+    {
+        Self {
+            priority_mapping: PriorityMapping::new(mapping),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_mapping(self, priority_mapping: PriorityMapping) -> Self {
+        Self {
+            priority_mapping,
+            ..self
+        }
+    }
+
+    /// Sets whether the source file and line of events are attached to their records. Defaults to `true`.
+    ///
+    /// Locations are only reported by backends supporting them, like `liblog` with the `api-30` feature.
+    pub fn with_location(self, location: bool) -> Self {
+        Self { location, ..self }
+    }
+
+    /// Sets what to do with records at the [fatal](Priority::Fatal) priority. Defaults to [`FatalPolicy::Log`].
+    pub fn with_fatal_policy(self, fatal_policy: FatalPolicy) -> Self {
+        Self {
+            fatal_policy,
+            ..self
+        }
+    }
+
+    /// Returns a [`Counter`] of the records dropped because of [`Overflow::SplitAtMost`].
+    pub fn dropped_count(&self) -> Counter {
+        self.dropped.clone()
+    }
+
+    /// Sets a callback invoked with every error encountered while writing a record.
+    ///
+    /// Errors are otherwise silently discarded, since writers are flushed when dropped.
+    ///
+    /// ```rust
+    /// use std::{ffi::CStr, io};
+    ///
+    /// use paranoid_android::{AndroidLogMakeWriter, Backend, Buffer, Priority, Record};
+    /// use tracing_subscriber::fmt::MakeWriter;
+    ///
+    /// #[derive(Debug)]
+    /// struct Rejecting;
+    ///
+    /// impl Backend for Rejecting {
+    ///     fn write(&self, _: &Record<'_>) -> io::Result<()> {
+    ///         Err(io::ErrorKind::PermissionDenied.into())
+    ///     }
+    /// }
+    ///
+    /// let make_writer = AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Crash, Rejecting)
+    ///     .with_error_handler(|e| assert_eq!(e.kind(), io::ErrorKind::PermissionDenied));
+    /// let errors = make_writer.error_count();
+    ///
+    /// io::Write::write_all(&mut make_writer.make_writer(), b"hello").unwrap();
+    /// assert_eq!(errors.get(), 1);
+    /// ```
+    pub fn with_error_handler(
+        mut self,
+        handler: impl Fn(&io::Error) + Send + Sync + 'static,
+    ) -> Self {
+        self.errors.callback = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns a [`Counter`] of the errors encountered while writing records.
+    pub fn error_count(&self) -> Counter {
+        self.errors.count.clone()
+    }
+
+    /// Returns the backend records are written to, shared with the filter keeping its minimum priority in sync.
+    pub(crate) fn backend(&self) -> &Arc<B> {
+        &self.backend
+    }
+
+    /// Returns a writer for records with the fallback tag, the given buffer and the given priority.
+    pub(crate) fn writer(&self, buffer: Buffer, priority: Priority) -> AndroidLogWriter<'_, B> {
+        self.writer_with_tag(self.tags.fallback().clone(), buffer, priority)
+    }
+
+    /// Returns an empty [`BufferedRecord`] with the tag, buffer and priority of an event with the given metadata,
+    /// or `None` if its records are dropped.
+    pub(crate) fn buffered(&self, meta: &Metadata<'_>) -> Option<BufferedRecord> {
+        let (tag, buffer, priority) = self.resolve(meta);
+        priority.map(|priority| BufferedRecord {
+            seq: 0,
+            message: Vec::new(),
+            tag,
             buffer,
+            priority,
+        })
+    }
+
+    /// Writes a [`BufferedRecord`] kept earlier.
+    pub(crate) fn replay(&self, record: BufferedRecord) {
+        let mut writer = self.writer_with_tag(record.tag, record.buffer, record.priority);
+        // failures are reported to the error handler of the writer
+        let _ = writer.write_all(&record.message);
+    }
+
+    /// Returns a writer for records with the given tag, buffer and priority.
+    fn writer_with_tag(
+        &self,
+        tag: Arc<CStr>,
+        buffer: Buffer,
+        priority: Priority,
+    ) -> AndroidLogWriter<'_, B> {
+        AndroidLogWriter {
+            tag,
+            make_writer: self,
+            message: PooledCString::empty().ok(),
+
+            buffer,
+            priority: Some(priority),
+            location: None,
+        }
+    }
+
+    /// Returns the tag, buffer and priority of the records of an event with the given metadata,
+    /// taking the [reserved fields](crate::ReservedFields) it was formatted with into account.
+    fn resolve(&self, meta: &Metadata<'_>) -> (Arc<CStr>, Buffer, Option<Priority>) {
+        let overrides = Overrides::take().unwrap_or_default();
+        let priority = match overrides.priority {
+            // captured `liblog` records were already written
+            #[cfg(all(target_os = "android", feature = "api-30"))]
+            _ if crate::capture::is_captured(meta) => None,
+            Some(priority) => Some(priority),
+            None => self.priority_mapping.map(meta),
+        };
+        let tag = overrides
+            .tag
+            .and_then(|tag| CString::new(tag).ok())
+            .map(Arc::from)
+            .unwrap_or_else(|| self.tags.get(meta));
+        (tag, overrides.buffer.unwrap_or(self.buffer), priority)
+    }
+
+    /// Returns the maximum length of the message of a record with the given tag.
+    fn max_len(&self, tag: &CStr) -> usize {
+        self.max_len.unwrap_or_else(|| {
+            // priority byte, tag and message NUL terminators
+            LOGGER_ENTRY_MAX_PAYLOAD
+                .saturating_sub(tag.to_bytes().len() + 3)
+                .max(MIN_MAX_LEN)
+        })
+    }
+
+    /// Splits a message into the pieces written as individual records,
+    /// applying the [`NulPolicy`], chunking them to fit logd's limits and marking them if needed.
+    fn pieces(
+        &self,
+        message: &[u8],
+        max_len: usize,
+    ) -> Result<SmallVec<[PooledCString; 4]>, Error> {
+        let mut replaced = None;
+        // the offsets of the `\0` escapes in the replaced message, which are never split across records
+        let mut escapes = SmallVec::<[usize; 4]>::new();
+        let segments: SmallVec<[&[u8]; 4]> = match self.nul_policy {
+            _ if !message.contains(&0) => smallvec![message],
+            NulPolicy::Split => message
+                .split(|&b| b == 0)
+                .filter(|s| !s.is_empty())
+                .collect(),
+            NulPolicy::Escape | NulPolicy::Replace => {
+                let replacement: &[u8] = match self.nul_policy {
+                    NulPolicy::Escape => b"\\0",
+                    _ => "\u{FFFD}".as_bytes(),
+                };
+
+                let replaced = replaced.insert(PooledCString::empty()?);
+                for (i, segment) in message.split(|&b| b == 0).enumerate() {
+                    if i > 0 {
+                        if self.nul_policy == NulPolicy::Escape {
+                            escapes.push(replaced.as_bytes().len());
+                        }
+                        replaced.write(replacement);
+                    }
+                    replaced.write(segment);
+                }
+                let replaced: &PooledCString = replaced;
+                SmallVec::from_buf([replaced.as_bytes()])
+                    .into_iter()
+                    .collect()
+            }
+        };
+
+        // never end a record on the backslash of an escape, unless it's all the record holds
+        let atomic = |offset: usize, len: usize| {
+            if len > 1 && escapes.binary_search(&(offset + len - 1)).is_ok() {
+                len - 1
+            } else {
+                len
+            }
+        };
+        let split = |segment: usize, max_len: usize| {
+            let data = segments[segment];
+            let mut raw: SmallVec<[(&[u8], bool); 4]> = SmallVec::new();
+            match self.overflow {
+                Overflow::Truncate if data.len() > max_len => {
+                    let len = truncate(data, max_len.saturating_sub(ELLIPSIS.len())).len();
+                    let len = atomic(0, len);
+                    raw.push((&data[..len], true))
+                }
+                Overflow::Truncate => raw.push((data, false)),
+                Overflow::Split | Overflow::SplitAtMost(_) => {
+                    let mut offset = 0;
+                    while let Some(chunk) = chunks(&data[offset..], max_len).next() {
+                        let len = atomic(offset, chunk.len());
+                        raw.push((&chunk[..len], false));
+                        offset += len;
+                    }
+                }
+            }
+            raw
+        };
+        let limit = match self.overflow {
+            Overflow::SplitAtMost(max_count) => max_count,
+            _ => usize::MAX,
+        };
+
+        // each segment is marked on its own, so that split parts of a message aren't reassembled into one
+        let mut pieces = SmallVec::new();
+        let mut dropped = 0;
+        for segment in 0..segments.len() {
+            let remaining = limit - pieces.len();
+
+            // markers take room from the chunks, which can in turn increase their count and the length of the markers
+            let mut raw = split(segment, max_len);
+            let mut id = None;
+            let mut reserved = 0;
+            while self.chunk_markers && raw.len().min(remaining) > 1 {
+                let count = raw.len().min(remaining);
+                let marker = Marker {
+                    index: count,
+                    count,
+                    id: *id.get_or_insert_with(|| {
+                        self.next_chunked_id.fetch_add(1, Ordering::Relaxed)
+                    }),
+                };
+                if marker.len() <= reserved {
+                    break;
+                }
+                reserved = marker.len();
+                raw = split(segment, max_len.saturating_sub(reserved));
+            }
+
+            if raw.len() > remaining {
+                dropped += raw.len() - remaining;
+                raw.truncate(remaining);
+            }
+
+            let id = id.filter(|_| raw.len() > 1);
+            let count = raw.len();
+            for (i, (chunk, truncated)) in raw.into_iter().enumerate() {
+                let mut piece = PooledCString::empty()?;
+                if let Some(id) = id {
+                    let marker = Marker {
+                        index: i + 1,
+                        count,
+                        id,
+                    };
+                    piece.write(marker.to_string().as_bytes());
+                }
+                piece.write(chunk);
+                if truncated {
+                    piece.write(ELLIPSIS.as_bytes());
+                }
+                pieces.push(piece);
+            }
+        }
+        if dropped > 0 {
+            self.dropped.add(dropped);
+        }
+        Ok(pieces)
+    }
+}
+
+impl Counter {
+    /// Returns the current value of the counter.
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn increment(&self) {
+        self.add(1);
+    }
+
+    fn add(&self, n: usize) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+impl ErrorHandler {
+    pub(crate) fn report(&self, e: &io::Error) {
+        self.count.increment();
+        if let Some(callback) = &self.callback {
+            callback(e);
+        }
+    }
+}
+
+impl PriorityMapping {
+    pub(crate) fn new<F>(mapping: F) -> Self
+    where
+        F: Fn(&Metadata<'_>) -> Option<Priority> + Send + Sync + 'static,
+    {
+        Self(Some(Box::new(mapping)))
+    }
+
+    fn map(&self, meta: &Metadata<'_>) -> Option<Priority> {
+        match &self.0 {
+            Some(mapping) => mapping(meta),
+            None => Some((*meta.level()).into()),
         }
     }
 }
 
+impl fmt::Debug for PriorityMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PriorityMapping")
+            .field(&self.0.as_ref().map(|_| ..))
+            .finish()
+    }
+}
+
+impl fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorHandler")
+            .field("count", &self.count.get())
+            .field("callback", &self.callback.as_ref().map(|_| ..))
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 struct PooledCString {
     buf: RefMut<'static, Vec<u8>>,
@@ -186,35 +841,36 @@ lazy_static! {
 }
 
 impl PooledCString {
-    fn empty() -> Self {
-        Self {
-            buf: BUFFER_POOL.create().unwrap(),
-        }
+    fn empty() -> Result<Self, Error> {
+        let buf = BUFFER_POOL.create().ok_or(Error::PoolExhausted)?;
+        Ok(Self { buf })
     }
 
-    fn new(data: &[u8]) -> Self {
-        let mut this = PooledCString::empty();
+    fn new(data: &[u8]) -> Result<Self, Error> {
+        let mut this = PooledCString::empty()?;
         this.write(data);
-        this
+        Ok(this)
     }
 
     fn write(&mut self, data: &[u8]) {
         self.buf.extend_from_slice(data);
     }
 
-    fn as_ptr(&mut self) -> Option<*const c_char> {
+    fn as_c_str(&mut self) -> Option<&CStr> {
         if self.buf.last().copied() != Some(0) {
             self.buf.push(0);
         }
 
-        CStr::from_bytes_with_nul(self.buf.as_ref())
-            .ok()
-            .map(CStr::as_ptr)
+        CStr::from_bytes_with_nul(self.buf.as_ref()).ok()
     }
 
     fn as_bytes(&self) -> &[u8] {
         self.buf.as_ref()
     }
+
+    fn clear(&mut self) {
+        self.buf.clear();
+    }
 }
 
 impl Drop for PooledCString {
@@ -241,3 +897,159 @@ impl<'a> Iterator for MessageIter<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::reassemble, Memory};
+
+    fn write(make_writer: &AndroidLogMakeWriter<Memory>, message: &[u8]) {
+        make_writer.make_writer().write_all(message).unwrap();
+    }
+
+    fn messages(memory: &Memory) -> Vec<String> {
+        memory.take().into_iter().map(|r| r.message).collect()
+    }
+
+    #[test]
+    fn long_tags_leave_room_for_a_message() {
+        let memory = Memory::new();
+        let make_writer =
+            AndroidLogMakeWriter::with_backend("t".repeat(4066), Buffer::Main, memory.clone())
+                .with_overflow(Overflow::Truncate);
+        write(&make_writer, b"hello");
+        assert_eq!(messages(&memory), ["h…"]);
+    }
+
+    #[test]
+    fn markers_are_sized_from_their_count_and_id() {
+        let memory = Memory::new();
+        let make_writer =
+            AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Main, memory.clone())
+                .with_max_len(20)
+                .with_chunk_markers(true);
+        let message = "ab".repeat(50);
+        write(&make_writer, message.as_bytes());
+
+        let records = messages(&memory);
+        // `[1/12 #0] ` leaves 9 bytes for the message
+        assert_eq!(records.len(), 12);
+        assert!(records.iter().all(|r| r.len() <= 20));
+        assert_eq!(reassemble(&records), [message]);
+    }
+
+    #[test]
+    fn markers_grow_with_the_count() {
+        let memory = Memory::new();
+        let make_writer =
+            AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Main, memory.clone())
+                .with_max_len(12)
+                .with_chunk_markers(true);
+        let message = "x".repeat(30);
+        write(&make_writer, message.as_bytes());
+
+        // `[1/9 #0] ` would leave 3 bytes, which takes 10 records, so `[1/10 #0] ` only leaves 1
+        let records = messages(&memory);
+        assert_eq!(records.len(), 30);
+        assert!(records.iter().all(|r| r.len() <= 12), "{:?}", records);
+        assert_eq!(reassemble(&records), [message]);
+    }
+
+    fn nul_writer(memory: &Memory, nul_policy: NulPolicy) -> AndroidLogMakeWriter<Memory> {
+        AndroidLogMakeWriter::with_backend("tag".to_owned(), Buffer::Main, memory.clone())
+            .with_max_len(8)
+            .with_nul_policy(nul_policy)
+    }
+
+    #[test]
+    fn nul_escape() {
+        let memory = Memory::new();
+        let make_writer = nul_writer(&memory, NulPolicy::Escape);
+
+        write(&make_writer, b"\0a\0\0b\0");
+        assert_eq!(messages(&memory), ["\\0a\\0\\0b", "\\0"]);
+
+        // the escape sequence is never split across records
+        write(&make_writer, b"abcdefg\0hij");
+        assert_eq!(messages(&memory), ["abcdefg", "\\0hij"]);
+        write(&make_writer, b"abcdef\0ghij");
+        assert_eq!(messages(&memory), ["abcdef\\0", "ghij"]);
+
+        // nor when truncated
+        let make_writer = make_writer.with_overflow(Overflow::Truncate);
+        write(&make_writer, b"abcd\0efgh");
+        assert_eq!(messages(&memory), ["abcd…"]);
+    }
+
+    #[test]
+    fn nul_replace() {
+        let memory = Memory::new();
+        let make_writer = nul_writer(&memory, NulPolicy::Replace);
+
+        write(&make_writer, b"a\0b");
+        assert_eq!(messages(&memory), ["a\u{FFFD}b"]);
+
+        // the replacement character is never split across records
+        for prefix in 5..=8 {
+            let mut message = "x".repeat(prefix).into_bytes();
+            message.extend_from_slice(b"\0yz");
+            write(&make_writer, &message);
+
+            let records = messages(&memory);
+            assert!(records.iter().all(|r| r.len() <= 8), "{:?}", records);
+            assert!(records.iter().all(|r| !r.contains('\0')), "{:?}", records);
+            assert_eq!(
+                records.concat(),
+                format!("{}\u{FFFD}yz", "x".repeat(prefix)),
+                "{:?}",
+                records
+            );
+        }
+    }
+
+    #[test]
+    fn nul_split() {
+        let memory = Memory::new();
+        let make_writer = nul_writer(&memory, NulPolicy::Split);
+
+        write(&make_writer, b"a\0b");
+        assert_eq!(messages(&memory), ["a", "b"]);
+
+        // leading, trailing and consecutive NULs don't produce empty records
+        write(&make_writer, b"\0\0a\0\0b\0");
+        assert_eq!(messages(&memory), ["a", "b"]);
+
+        write(&make_writer, b"\0\0");
+        assert!(messages(&memory).is_empty());
+
+        // NULs right at and around a chunk boundary
+        write(&make_writer, b"abcdefgh\0ijk");
+        assert_eq!(messages(&memory), ["abcdefgh", "ijk"]);
+        write(&make_writer, b"abcdefghi\0jk");
+        assert_eq!(messages(&memory), ["abcdefgh", "i", "jk"]);
+        write(&make_writer, b"abcdefg\0hijklmnop");
+        assert_eq!(messages(&memory), ["abcdefg", "hijklmno", "p"]);
+    }
+
+    #[test]
+    fn nul_split_with_markers() {
+        let memory = Memory::new();
+        let make_writer = nul_writer(&memory, NulPolicy::Split)
+            .with_max_len(16)
+            .with_chunk_markers(true);
+
+        write(
+            &make_writer,
+            b"first\0the second part, longer\0and the third one\0",
+        );
+        let records = messages(&memory);
+        assert!(records.iter().all(|r| r.len() <= 16), "{:?}", records);
+        assert_eq!(records[0], "first");
+        assert!(records[1..5].iter().all(|r| r.contains("/4 #0] ")));
+        assert!(records[5..].iter().all(|r| r.contains("/3 #1] ")));
+        assert_eq!(
+            reassemble(&records),
+            ["first", "the second part, longer", "and the third one"]
+        );
+    }
+}